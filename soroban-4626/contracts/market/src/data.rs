@@ -20,6 +20,7 @@ pub struct MarketData {
     pub lock_period_in_seconds: u64,
     pub event_threshold_in_seconds: u64,
     pub unlock_period_in_seconds: u64,
+    pub decimals_offset: u32,
 }
 
 #[derive(Clone)]
@@ -36,4 +36,11 @@ pub enum MarketError {
     ActualEventTimeNotSet = 30,
     LiquidatedTimeNotSet = 31,
     MaturityTimeNotSet = 32,
+    HedgeVaultMarketSetupFailed = 33,
+    RiskVaultMarketSetupFailed = 34,
+    SettlementFailed = 35,
+    NoPendingAdmin = 36,
+    VaultAdminTransferFailed = 37,
+    AlreadyMigrated = 38,
+    VaultRescheduleFailed = 39,
 }
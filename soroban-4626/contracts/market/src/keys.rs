@@ -45,4 +45,7 @@ pub enum MarketDataKey {
     EventThresholdInSeconds,
     UnlockInSeconds,
     ActualEventUnixTimestamp,
+    PendingAdminAddress,
+    RotateVaultAdmins,
+    SchemaVersion,
 }
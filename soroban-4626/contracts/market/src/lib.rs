@@ -5,3 +5,5 @@ mod errors;
 mod keys;
 mod market;
 mod storage;
+
+mod test;
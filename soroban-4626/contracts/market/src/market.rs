@@ -22,7 +22,7 @@
 // Unix time converter, example: https://www.unixtimestamp.com/
 // Market lifecycle: Live -> Liquidate or Mature -> Liquidated or Matured
 use soroban_sdk::{
-    contract, contractimpl, contractmeta, symbol_short, token, Address, Env, String, Symbol,
+    contract, contractimpl, contractmeta, symbol_short, Address, BytesN, Env, String, Symbol,
 };
 
 use vault::vault::VaultContractClient;
@@ -38,14 +38,17 @@ use crate::{
         read_commission_fee, read_description, read_event_threshold_seconds, read_event_timestamp,
         read_hedge_vault, read_initialized_time, read_is_automatic, read_last_keeper_time,
         read_last_oracle_time, read_liquidated_time, read_lock_seconds, read_matured_time,
-        read_name, read_oracle_address, read_oracle_name, read_risk_score, read_risk_vault,
-        read_status, read_unlock_seconds, remove_is_paused, write_actual_event_timestamp,
-        write_administrator, write_asset, write_commission_fee, write_description,
-        write_event_threshold_seconds, write_event_timestamp, write_hedge_vault,
-        write_initialized_time, write_is_automatic, write_is_paused, write_last_keeper_time,
-        write_last_oracle_time, write_liquidated_time, write_lock_seconds, write_matured_time,
-        write_name, write_oracle_address, write_oracle_name, write_risk_score, write_risk_vault,
-        write_status, write_unlock_seconds, BUMP_THRESHOLD, EXTEND_TO_DAYS,
+        read_name, read_oracle_address, read_oracle_name, read_pending_administrator,
+        read_risk_score, read_risk_vault, read_schema_version, read_status, read_unlock_seconds,
+        remove_is_paused, remove_pending_administrator, remove_rotate_vault_admins,
+        rotate_vault_admins, write_actual_event_timestamp, write_administrator, write_asset,
+        write_commission_fee, write_description, write_event_threshold_seconds,
+        write_event_timestamp, write_hedge_vault, write_initialized_time, write_is_automatic,
+        write_is_paused, write_last_keeper_time, write_last_oracle_time, write_liquidated_time,
+        write_lock_seconds, write_matured_time, write_name, write_oracle_address,
+        write_oracle_name, write_pending_administrator, write_risk_score, write_risk_vault,
+        write_rotate_vault_admins, write_schema_version, write_status, write_unlock_seconds,
+        BUMP_THRESHOLD, EXTEND_TO_DAYS,
     },
 };
 
@@ -65,6 +68,7 @@ const MIN_EVENT_THRESHOLD_IN_SECONDS: u64 = 0;
 const MAX_EVENT_THRESHOLD_IN_SECONDS: u64 = 86400; // 1 day
 const MIN_UNLOCK_IN_SECONDS: u64 = 0;
 const MAX_UNLOCK_IN_SECONDS: u64 = 604800; // 7 days
+const SCHEMA_VERSION: u32 = 1; // Storage layout version, bump together with a new step in migrate_from
 
 #[allow(dead_code)]
 #[contractimpl]
@@ -133,6 +137,8 @@ impl MarketContract {
                 &data.asset_address,
                 &lock_timestamp,
                 &unlock_timestamp,
+                &data.decimals_offset,
+                &None,
             )
             .map_err(|_| MarketError::HedgeVaultInitializationFailed)?;
 
@@ -142,30 +148,20 @@ impl MarketContract {
                 &data.asset_address,
                 &lock_timestamp,
                 &unlock_timestamp,
+                &data.decimals_offset,
+                &None,
             )
             .map_err(|_| MarketError::RiskVaultInitializationFailed)?;
 
-        // Approve asset allowance between hedge and risk vaults
-        // The maximum TTL (Time To Live) for token allowance approval is capped at some ledgers.
-        // For now hard-coded 17280, which is equivalent to approximately 1 day.
-        // live_until must be >= ledger sequence
+        // Register this market with both vaults, settlement moves collateral through them
+        // so that their internal asset accounting stays in sync
         _ = hedge_vault
-            .try_approve_asset_allowance(
-                &data.asset_address,
-                &data.risk_vault_address,
-                &i128::MAX,
-                &(env.ledger().sequence() + 17280),
-            )
-            .map_err(|_| MarketError::HedgeVaultAllowanceFailed)?;
+            .try_set_market(&env.current_contract_address())
+            .map_err(|_| MarketError::HedgeVaultMarketSetupFailed)?;
 
         _ = risk_vault
-            .try_approve_asset_allowance(
-                &data.asset_address,
-                &data.hedge_vault_address,
-                &i128::MAX,
-                &(env.ledger().sequence() + 17280),
-            )
-            .map_err(|_| MarketError::RiskVaultAllowanceFailed)?;
+            .try_set_market(&env.current_contract_address())
+            .map_err(|_| MarketError::RiskVaultMarketSetupFailed)?;
 
         // Persist State
         write_administrator(&env, &data.admin_address);
@@ -185,6 +181,7 @@ impl MarketContract {
         write_lock_seconds(&env, &data.lock_period_in_seconds);
         write_event_threshold_seconds(&env, &data.event_threshold_in_seconds);
         write_unlock_seconds(&env, &data.unlock_period_in_seconds);
+        write_schema_version(&env, &SCHEMA_VERSION);
 
         // Extend TTL
         extend_contract_ttl(&env, BUMP_THRESHOLD, EXTEND_TO_DAYS);
@@ -217,6 +214,72 @@ impl MarketContract {
         Ok(read_administrator(&env))
     }
 
+    pub fn pending_admin_address(env: Env) -> Result<Option<Address>, MarketError> {
+        Self::check_is_initialized(&env)?;
+        Ok(read_pending_administrator(&env))
+    }
+
+    pub fn propose_admin(
+        env: Env,
+        new_admin: Address,
+        rotate_vaults: bool,
+    ) -> Result<bool, MarketError> {
+        // Administrator changes only once the new address accepts. With rotate_vaults the
+        // same transfer is proposed on both vaults and completes together with this one.
+        Self::check_is_initialized(&env)?;
+        let admin: Address = read_administrator(&env);
+        admin.require_auth();
+        if rotate_vaults {
+            Self::for_each_vault(&env, |vault| {
+                vault.try_propose_administrator(&new_admin).is_ok()
+            })?;
+            write_rotate_vault_admins(&env);
+        } else if rotate_vault_admins(&env) {
+            // Drop a rotation proposed together with an earlier transfer
+            Self::for_each_vault(&env, |vault| {
+                vault.try_cancel_administrator_transfer().is_ok()
+            })?;
+            remove_rotate_vault_admins(&env);
+        }
+        write_pending_administrator(&env, &new_admin);
+        Self::emit_admin_proposed_event(&env, &admin, &new_admin, rotate_vaults);
+        Ok(true)
+    }
+
+    pub fn accept_admin(env: Env) -> Result<bool, MarketError> {
+        Self::check_is_initialized(&env)?;
+        let new_admin: Address =
+            read_pending_administrator(&env).ok_or(MarketError::NoPendingAdmin)?;
+        new_admin.require_auth();
+        let rotate_vaults: bool = rotate_vault_admins(&env);
+        if rotate_vaults {
+            Self::for_each_vault(&env, |vault| vault.try_accept_administrator().is_ok())?;
+            remove_rotate_vault_admins(&env);
+        }
+        let previous_admin: Address = read_administrator(&env);
+        write_administrator(&env, &new_admin);
+        remove_pending_administrator(&env);
+        Self::emit_admin_accepted_event(&env, &previous_admin, &new_admin, rotate_vaults);
+        Ok(true)
+    }
+
+    pub fn cancel_admin_transfer(env: Env) -> Result<bool, MarketError> {
+        Self::check_is_initialized(&env)?;
+        let admin: Address = read_administrator(&env);
+        admin.require_auth();
+        let pending: Address =
+            read_pending_administrator(&env).ok_or(MarketError::NoPendingAdmin)?;
+        if rotate_vault_admins(&env) {
+            Self::for_each_vault(&env, |vault| {
+                vault.try_cancel_administrator_transfer().is_ok()
+            })?;
+            remove_rotate_vault_admins(&env);
+        }
+        remove_pending_administrator(&env);
+        Self::emit_admin_cancelled_event(&env, &admin, &pending);
+        Ok(true)
+    }
+
     pub fn current_contract_address(env: Env) -> Address {
         env.current_contract_address()
     }
@@ -282,6 +345,40 @@ impl MarketContract {
         Ok(read_actual_event_timestamp(&env))
     }
 
+    pub fn reschedule_event(env: Env, event_unix_timestamp: u64) -> Result<bool, MarketError> {
+        // Moves the expected event time and pushes the recomputed lock window to both vaults,
+        // only possible until the vaults lock for the currently scheduled event
+        Self::check_is_initialized(&env)?;
+        Self::ensure_not_paused(&env)?;
+        let admin: Address = read_administrator(&env);
+        admin.require_auth();
+        Self::ensure_not_liquidated_or_matured(&env)?;
+        let current_timestamp: u64 = env.ledger().timestamp();
+        if event_unix_timestamp < current_timestamp {
+            return Err(MarketError::InvalidUnixTimestamp);
+        }
+        let previous_event: u64 = read_event_timestamp(&env);
+        let (previous_lock, _) = Self::lock_window_of(&env, previous_event);
+        if current_timestamp >= previous_lock {
+            return Err(MarketError::AlreadyLocked);
+        }
+        let (lock_timestamp, unlock_timestamp) = Self::lock_window_of(&env, event_unix_timestamp);
+        let market: Address = env.current_contract_address();
+        for vault in [read_hedge_vault(&env), read_risk_vault(&env)] {
+            _ = VaultContractClient::new(&env, &vault)
+                .try_reschedule_lock_window(
+                    &market,
+                    &previous_lock,
+                    &lock_timestamp,
+                    &unlock_timestamp,
+                )
+                .map_err(|_| MarketError::VaultRescheduleFailed)?;
+        }
+        write_event_timestamp(&env, &event_unix_timestamp);
+        Self::emit_event_rescheduled_event(&env, &admin, previous_event, event_unix_timestamp);
+        Ok(true)
+    }
+
     pub fn time_until_event(env: Env) -> Result<u64, MarketError> {
         Self::check_is_initialized(&env)?;
         let current_timestamp: u64 = env.ledger().timestamp();
@@ -324,22 +421,15 @@ impl MarketContract {
 
     pub fn time_of_lock(env: Env) -> Result<u64, MarketError> {
         Self::check_is_initialized(&env)?;
-        let lock: u64 = read_lock_seconds(&env);
         let event: u64 = read_event_timestamp(&env);
-        let lock_timestamp: u64 = event.checked_sub(lock).unwrap();
+        let (lock_timestamp, _) = Self::lock_window_of(&env, event);
         Ok(lock_timestamp)
     }
 
     pub fn time_of_unlock(env: Env) -> Result<u64, MarketError> {
         Self::check_is_initialized(&env)?;
-        let unlock: u64 = read_unlock_seconds(&env);
         let event: u64 = read_event_timestamp(&env);
-        let threshold: u64 = read_event_threshold_seconds(&env);
-        let unlock_timestamp: u64 = event
-            .checked_add(threshold)
-            .unwrap()
-            .checked_add(unlock)
-            .unwrap();
+        let (_, unlock_timestamp) = Self::lock_window_of(&env, event);
         Ok(unlock_timestamp)
     }
 
@@ -543,8 +633,7 @@ impl MarketContract {
         // If maturity is triggered: Hedge collateral is transferred to the Risk Vault.
         let hedge: Address = read_hedge_vault(&env);
         let risk: Address = read_risk_vault(&env);
-        let asset_address: Address = read_asset(&env);
-        Self::transfer_asset(&env, &asset_address, &hedge, &risk)?;
+        Self::transfer_asset(&env, &hedge, &risk)?;
         // Emit event
         let name: String = read_name(&env);
         Self::emit_matured_event(&env, &hedge, &risk, name, current_timestamp);
@@ -568,8 +657,7 @@ impl MarketContract {
         // If maturity is triggered: Hedge collateral is transferred to the Risk Vault.
         let hedge: Address = read_hedge_vault(&env);
         let risk: Address = read_risk_vault(&env);
-        let asset_address: Address = read_asset(&env);
-        Self::transfer_asset(&env, &asset_address, &risk, &hedge)?;
+        Self::transfer_asset(&env, &risk, &hedge)?;
         // Emit event
         let name: String = read_name(&env);
         Self::emit_liquidated_event(&env, &hedge, &risk, name, current_timestamp);
@@ -616,9 +704,9 @@ impl MarketContract {
         let hedge: Address = read_hedge_vault(&env);
         let risk: Address = read_risk_vault(&env);
         let vault = VaultContractClient::new(&env, &hedge);
-        let token_client = token::Client::new(&env, &read_asset(&env));
-        let assets_hedge: i128 = token_client.balance(&hedge);
-        let assets_risk: i128 = token_client.balance(&risk);
+        // Use the assets tracked by the vaults, these are what settlement distributes
+        let assets_hedge: i128 = VaultContractClient::new(&env, &hedge).total_assets();
+        let assets_risk: i128 = VaultContractClient::new(&env, &risk).total_assets();
         let fee_percentage: u32 = read_commission_fee(&env);
         let admin_fee_hedge: i128 = Self::calculate_fee_amount(assets_hedge, fee_percentage);
         let admin_fee_risk: i128 = Self::calculate_fee_amount(assets_risk, fee_percentage);
@@ -643,9 +731,9 @@ impl MarketContract {
         let hedge: Address = read_hedge_vault(&env);
         let risk: Address = read_risk_vault(&env);
         let vault = VaultContractClient::new(&env, &risk);
-        let token_client = token::Client::new(&env, &read_asset(&env));
-        let assets_hedge: i128 = token_client.balance(&hedge);
-        let assets_risk: i128 = token_client.balance(&risk);
+        // Use the assets tracked by the vaults, these are what settlement distributes
+        let assets_hedge: i128 = VaultContractClient::new(&env, &hedge).total_assets();
+        let assets_risk: i128 = VaultContractClient::new(&env, &risk).total_assets();
         let fee_percentage: u32 = read_commission_fee(&env);
         let admin_fee_hedge: i128 = Self::calculate_fee_amount(assets_hedge, fee_percentage);
         let admin_fee_risk: i128 = Self::calculate_fee_amount(assets_risk, fee_percentage);
@@ -695,6 +783,38 @@ impl MarketContract {
         Err(MarketError::ContractIsAlreadyUnpaused)
     }
 
+    pub fn upgrade(env: Env, new_wasm_hash: BytesN<32>) -> Result<bool, MarketError> {
+        // Replaces the contract code, storage is kept and converted afterwards by calling migrate
+        Self::check_is_initialized(&env)?;
+        let admin: Address = read_administrator(&env);
+        admin.require_auth();
+        env.deployer()
+            .update_current_contract_wasm(new_wasm_hash.clone());
+        Self::emit_upgraded_event(&env, &admin, new_wasm_hash);
+        Ok(true)
+    }
+
+    pub fn schema_version(env: Env) -> u32 {
+        read_schema_version(&env)
+    }
+
+    pub fn migrate(env: Env) -> Result<u32, MarketError> {
+        // Converts storage written by earlier versions one step at a time, up to the current layout
+        Self::check_is_initialized(&env)?;
+        let admin: Address = read_administrator(&env);
+        admin.require_auth();
+        let previous: u32 = read_schema_version(&env);
+        if previous >= SCHEMA_VERSION {
+            return Err(MarketError::AlreadyMigrated);
+        }
+        for version in previous..SCHEMA_VERSION {
+            Self::migrate_from(&env, version)?;
+        }
+        write_schema_version(&env, &SCHEMA_VERSION);
+        Self::emit_schema_migrated_event(&env, &admin, previous, SCHEMA_VERSION);
+        Ok(SCHEMA_VERSION)
+    }
+
     pub fn extend_market_ttl(env: &Env) -> Result<bool, MarketError> {
         // Anyone can call this function to extend time-to-live
         if has_administrator(&env) {
@@ -776,6 +896,16 @@ impl MarketContract {
         Ok(())
     }
 
+    fn lock_window_of(env: &Env, event_timestamp: u64) -> (u64, u64) {
+        let lock_timestamp: u64 = event_timestamp.checked_sub(read_lock_seconds(env)).unwrap();
+        let unlock_timestamp: u64 = event_timestamp
+            .checked_add(read_event_threshold_seconds(env))
+            .unwrap()
+            .checked_add(read_unlock_seconds(env))
+            .unwrap();
+        (lock_timestamp, unlock_timestamp)
+    }
+
     fn ensure_not_paused(env: &Env) -> Result<(), MarketError> {
         match is_paused(env) {
             true => Err(MarketError::ContractIsAlreadyPaused),
@@ -824,43 +954,96 @@ impl MarketContract {
 
     fn transfer_asset(
         env: &Env,
-        asset_address: &Address,
         from_vault: &Address,
         to_vault: &Address,
     ) -> Result<(), MarketError> {
-        // Note: before calling this function, make sure that vaults have enabled full transfer allowance of the underlying asset between each other
-        let token_client = token::Client::new(&env, &asset_address);
-        let allowance_1: i128 = token_client.allowance(&from_vault, &to_vault);
-        let balance_1: i128 = token_client.balance(&from_vault);
-        if balance_1 > allowance_1 {
-            return Err(MarketError::InsufficientAllowance);
-        }
+        // Collateral and commission are paid out by the vaults themselves, so that each vault
+        // updates its tracked assets. Tokens sent to a vault directly are not part of the settlement.
+        let from_client = VaultContractClient::new(env, from_vault);
+        let to_client = VaultContractClient::new(env, to_vault);
+        let balance_1: i128 = from_client.total_assets();
         let fee_percentage: u32 = read_commission_fee(&env);
         if fee_percentage > 0 {
             let admin_fee_amount_1: i128 = Self::calculate_fee_amount(balance_1, fee_percentage);
-            let balance_2: i128 = token_client.balance(&to_vault);
+            let balance_2: i128 = to_client.total_assets();
             let admin_fee_amount_2: i128 = Self::calculate_fee_amount(balance_2, fee_percentage);
-            let allowance_2: i128 = token_client.allowance(&to_vault, &from_vault);
-            if balance_2 > allowance_2 {
-                return Err(MarketError::InsufficientAllowanceForFeeTransfer);
-            }
             let admin: Address = read_administrator(&env);
             // Make sure transfers happen after all the calculations are done
             if balance_1 - admin_fee_amount_1 > 0 {
                 // Transfer asset amount minus fee amount from one vault to another
-                token_client.transfer(&from_vault, &to_vault, &(balance_1 - admin_fee_amount_1));
+                Self::move_collateral(
+                    &from_client,
+                    &to_client,
+                    to_vault,
+                    balance_1 - admin_fee_amount_1,
+                )?;
             }
             if admin_fee_amount_1 > 0 {
-                // Transfer fee amount to market administrator (vault must already have the allowance)
-                token_client.transfer_from(&to_vault, &from_vault, &admin, &admin_fee_amount_1);
+                // Transfer fee amount to market administrator
+                _ = from_client
+                    .try_settle_out(&admin, &admin_fee_amount_1)
+                    .map_err(|_| MarketError::SettlementFailed)?;
             }
             if admin_fee_amount_2 > 0 {
-                // Another vault also needs to transfer fee amount to market administrator (vault must already have the allowance)
-                token_client.transfer_from(&from_vault, &to_vault, &admin, &admin_fee_amount_2);
+                // Another vault also needs to transfer fee amount to market administrator
+                _ = to_client
+                    .try_settle_out(&admin, &admin_fee_amount_2)
+                    .map_err(|_| MarketError::SettlementFailed)?;
             }
-        } else {
+        } else if balance_1 > 0 {
             // Transfer whole asset amount from one vault to another. No admin fee was configured.
-            token_client.transfer(&from_vault, &to_vault, &balance_1);
+            Self::move_collateral(&from_client, &to_client, to_vault, balance_1)?;
+        }
+        Ok(())
+    }
+
+    fn move_collateral(
+        from_client: &VaultContractClient,
+        to_client: &VaultContractClient,
+        to_vault: &Address,
+        amount: i128,
+    ) -> Result<(), MarketError> {
+        _ = from_client
+            .try_settle_out(to_vault, &amount)
+            .map_err(|_| MarketError::SettlementFailed)?;
+        _ = to_client
+            .try_settle_in(&amount)
+            .map_err(|_| MarketError::SettlementFailed)?;
+        Ok(())
+    }
+
+    fn migrate_from(env: &Env, version: u32) -> Result<(), MarketError> {
+        match version {
+            // Markets deployed before settlement went through the vaults never registered with them
+            0 => {
+                let market: Address = env.current_contract_address();
+                let hedge_vault = VaultContractClient::new(env, &read_hedge_vault(env));
+                if hedge_vault.try_market_address() != Ok(Ok(market.clone())) {
+                    _ = hedge_vault
+                        .try_set_market(&market)
+                        .map_err(|_| MarketError::HedgeVaultMarketSetupFailed)?;
+                }
+                let risk_vault = VaultContractClient::new(env, &read_risk_vault(env));
+                if risk_vault.try_market_address() != Ok(Ok(market.clone())) {
+                    _ = risk_vault
+                        .try_set_market(&market)
+                        .map_err(|_| MarketError::RiskVaultMarketSetupFailed)?;
+                }
+                Ok(())
+            }
+            _ => Err(MarketError::AlreadyMigrated),
+        }
+    }
+
+    fn for_each_vault<F>(env: &Env, mut call: F) -> Result<(), MarketError>
+    where
+        F: FnMut(&VaultContractClient) -> bool,
+    {
+        // Used to keep the vault administrators in step with the market administrator
+        let hedge_vault = VaultContractClient::new(env, &read_hedge_vault(env));
+        let risk_vault = VaultContractClient::new(env, &read_risk_vault(env));
+        if !call(&hedge_vault) || !call(&risk_vault) {
+            return Err(MarketError::VaultAdminTransferFailed);
         }
         Ok(())
     }
@@ -939,6 +1122,46 @@ impl MarketContract {
         env.events().publish(topics, (name, timestamp));
     }
 
+    fn emit_event_rescheduled_event(env: &Env, admin: &Address, previous: u64, current: u64) {
+        let topics = (symbol_short!("resched"), admin);
+        env.events().publish(topics, (previous, current));
+    }
+
+    fn emit_upgraded_event(env: &Env, admin: &Address, new_wasm_hash: BytesN<32>) {
+        let topics = (symbol_short!("upgrade"), admin);
+        env.events().publish(topics, new_wasm_hash);
+    }
+
+    fn emit_schema_migrated_event(env: &Env, admin: &Address, previous: u32, current: u32) {
+        let topics = (symbol_short!("schema"), admin);
+        env.events().publish(topics, (previous, current));
+    }
+
+    fn emit_admin_proposed_event(
+        env: &Env,
+        admin: &Address,
+        new_admin: &Address,
+        rotate_vaults: bool,
+    ) {
+        let topics = (symbol_short!("adm_prop"), admin, new_admin);
+        env.events().publish(topics, rotate_vaults);
+    }
+
+    fn emit_admin_accepted_event(
+        env: &Env,
+        previous_admin: &Address,
+        new_admin: &Address,
+        rotate_vaults: bool,
+    ) {
+        let topics = (symbol_short!("adm_acpt"), previous_admin, new_admin);
+        env.events().publish(topics, rotate_vaults);
+    }
+
+    fn emit_admin_cancelled_event(env: &Env, admin: &Address, pending_admin: &Address) {
+        let topics = (symbol_short!("adm_cncl"), admin, pending_admin);
+        env.events().publish(topics, ());
+    }
+
     fn _emit_init_event(env: &Env, admin: &Address, name: String, timestamp: u64) {
         let topics = (symbol_short!("init"), admin);
         env.events().publish(topics, (name, timestamp));
@@ -19,6 +19,47 @@ pub fn write_administrator(e: &Env, address: &Address) {
     e.storage().instance().set(&key, address);
 }
 
+pub fn read_schema_version(e: &Env) -> u32 {
+    // Markets deployed before schema versioning have no version stored
+    let key = MarketDataKey::SchemaVersion;
+    e.storage().instance().get(&key).unwrap_or(0)
+}
+
+pub fn write_schema_version(e: &Env, version: &u32) {
+    let key = MarketDataKey::SchemaVersion;
+    e.storage().instance().set(&key, version);
+}
+
+pub fn read_pending_administrator(e: &Env) -> Option<Address> {
+    let key = MarketDataKey::PendingAdminAddress;
+    e.storage().instance().get(&key)
+}
+
+pub fn write_pending_administrator(e: &Env, address: &Address) {
+    let key = MarketDataKey::PendingAdminAddress;
+    e.storage().instance().set(&key, address);
+}
+
+pub fn remove_pending_administrator(e: &Env) {
+    let key = MarketDataKey::PendingAdminAddress;
+    e.storage().instance().remove(&key);
+}
+
+pub fn rotate_vault_admins(e: &Env) -> bool {
+    let key = MarketDataKey::RotateVaultAdmins;
+    e.storage().instance().has(&key)
+}
+
+pub fn write_rotate_vault_admins(e: &Env) {
+    let key = MarketDataKey::RotateVaultAdmins;
+    e.storage().instance().set(&key, &())
+}
+
+pub fn remove_rotate_vault_admins(e: &Env) {
+    let key = MarketDataKey::RotateVaultAdmins;
+    e.storage().instance().remove(&key);
+}
+
 pub fn read_status(e: &Env) -> MarketStatus {
     let key = MarketDataKey::Status;
     e.storage().instance().get(&key).unwrap()
@@ -0,0 +1,239 @@
+#![cfg(test)]
+extern crate std;
+
+use soroban_sdk::{
+    testutils::{
+        Address as _, AuthorizedFunction, AuthorizedInvocation, Ledger, MockAuth, MockAuthInvoke,
+    },
+    Address, Env, IntoVal, InvokeError, String, Symbol,
+};
+use vault::{
+    roles::Role,
+    vault::{Vault, VaultClient},
+};
+
+use crate::{
+    data::MarketData,
+    errors::MarketError,
+    keys::{MarketDataKey, MarketRisk},
+    market::{MarketContract, MarketContractClient},
+};
+
+const EVENT_TIMESTAMP: u64 = 1_000_000;
+
+struct Setup<'a> {
+    env: Env,
+    admin: Address,
+    market: MarketContractClient<'a>,
+    hedge_vault: VaultClient<'a>,
+    risk_vault: VaultClient<'a>,
+}
+
+fn setup<'a>() -> Setup<'a> {
+    let env = Env::default();
+    env.mock_all_auths();
+    let admin = Address::generate(&env);
+    let asset_address = env
+        .register_stellar_asset_contract_v2(admin.clone())
+        .address();
+    let hedge_vault = VaultClient::new(&env, &env.register_contract(None, Vault));
+    let risk_vault = VaultClient::new(&env, &env.register_contract(None, Vault));
+    let market = MarketContractClient::new(&env, &env.register_contract(None, MarketContract));
+    market.init(&MarketData {
+        name: String::from_str(&env, "test"),
+        description: String::from_str(&env, "desc"),
+        admin_address: admin.clone(),
+        asset_address,
+        trusted_oracle_name: String::from_str(&env, "oracle"),
+        trusted_oracle_address: Address::generate(&env),
+        hedge_vault_address: hedge_vault.address.clone(),
+        risk_vault_address: risk_vault.address.clone(),
+        commission_fee: 10,
+        risk_score: MarketRisk::LOW,
+        is_automatic: true,
+        event_unix_timestamp: EVENT_TIMESTAMP,
+        lock_period_in_seconds: 600,
+        event_threshold_in_seconds: 600,
+        unlock_period_in_seconds: 600,
+        decimals_offset: 0,
+    });
+    Setup {
+        env,
+        admin,
+        market,
+        hedge_vault,
+        risk_vault,
+    }
+}
+
+#[test]
+fn test_admin_transfer_without_vault_rotation() {
+    let setup = setup();
+    let new_admin = Address::generate(&setup.env);
+    setup.market.propose_admin(&new_admin, &false);
+    assert_eq!(
+        setup.market.pending_admin_address(),
+        Some(new_admin.clone())
+    );
+    assert_eq!(setup.market.admin_address(), setup.admin);
+
+    setup.market.accept_admin();
+    assert_eq!(setup.market.admin_address(), new_admin);
+    assert_eq!(setup.market.pending_admin_address(), None);
+    assert_eq!(setup.hedge_vault.administrator_address(), setup.admin);
+    assert_eq!(setup.risk_vault.administrator_address(), setup.admin);
+    assert_eq!(
+        setup.market.try_accept_admin(),
+        Err(Ok(MarketError::NoPendingAdmin))
+    );
+}
+
+#[test]
+fn test_admin_transfer_rotates_vault_admins() {
+    let setup = setup();
+    let new_admin = Address::generate(&setup.env);
+    setup.market.propose_admin(&new_admin, &true);
+    assert_eq!(
+        setup.hedge_vault.pending_administrator(),
+        Some(new_admin.clone())
+    );
+    assert_eq!(
+        setup.risk_vault.pending_administrator(),
+        Some(new_admin.clone())
+    );
+
+    setup.market.accept_admin();
+    assert_eq!(setup.market.admin_address(), new_admin);
+    assert_eq!(setup.hedge_vault.administrator_address(), new_admin);
+    assert_eq!(setup.risk_vault.administrator_address(), new_admin);
+    assert_eq!(setup.hedge_vault.pending_administrator(), None);
+}
+
+#[test]
+fn test_cancel_admin_transfer_cancels_vault_rotation() {
+    let setup = setup();
+    let new_admin = Address::generate(&setup.env);
+    setup.market.propose_admin(&new_admin, &true);
+    setup.market.cancel_admin_transfer();
+    assert_eq!(setup.market.pending_admin_address(), None);
+    assert_eq!(setup.hedge_vault.pending_administrator(), None);
+    assert_eq!(setup.risk_vault.pending_administrator(), None);
+    assert_eq!(
+        setup.market.try_cancel_admin_transfer(),
+        Err(Ok(MarketError::NoPendingAdmin))
+    );
+}
+
+#[test]
+fn test_migrate_from_legacy_layout() {
+    let setup = setup();
+    assert_eq!(setup.market.schema_version(), 1);
+    assert_eq!(
+        setup.market.try_migrate(),
+        Err(Ok(MarketError::AlreadyMigrated))
+    );
+
+    // Markets deployed before versioning were not registered with their vaults
+    let market_address = setup.market.address.clone();
+    let other_market = Address::generate(&setup.env);
+    setup.hedge_vault.set_market(&other_market);
+    setup.risk_vault.set_market(&other_market);
+    setup.env.as_contract(&market_address, || {
+        let storage = setup.env.storage().instance();
+        storage.remove(&MarketDataKey::SchemaVersion);
+    });
+    assert_eq!(setup.market.schema_version(), 0);
+
+    assert_eq!(setup.market.migrate(), 1);
+    assert_eq!(setup.market.schema_version(), 1);
+    assert_eq!(setup.hedge_vault.market_address(), market_address);
+    assert_eq!(setup.risk_vault.market_address(), market_address);
+    assert!(setup
+        .hedge_vault
+        .has_role(&Role::MarketOperator, &market_address));
+    assert!(!setup
+        .risk_vault
+        .has_role(&Role::MarketOperator, &other_market));
+}
+
+#[test]
+fn test_upgrade_then_migrate() {
+    let setup = setup();
+    // Running the uploaded code in the VM costs more than the default test budget
+    setup.env.budget().reset_unlimited();
+    // Release build of this contract, regenerated with `make testdata`
+    let wasm_hash = setup
+        .env
+        .deployer()
+        .upload_contract_wasm(include_bytes!("../testdata/market.wasm").as_slice());
+
+    // Only the administrator can replace the code
+    let stranger = Address::generate(&setup.env);
+    setup.env.mock_auths(&[MockAuth {
+        address: &stranger,
+        invoke: &MockAuthInvoke {
+            contract: &setup.market.address,
+            fn_name: "upgrade",
+            args: (wasm_hash.clone(),).into_val(&setup.env),
+            sub_invokes: &[],
+        },
+    }]);
+    assert_eq!(
+        setup.market.try_upgrade(&wasm_hash),
+        Err(Err(InvokeError::Abort))
+    );
+
+    setup.env.mock_all_auths();
+    assert!(setup.market.upgrade(&wasm_hash));
+    assert_eq!(
+        setup.env.auths(),
+        std::vec![(
+            setup.admin.clone(),
+            AuthorizedInvocation {
+                function: AuthorizedFunction::Contract((
+                    setup.market.address.clone(),
+                    Symbol::new(&setup.env, "upgrade"),
+                    (wasm_hash.clone(),).into_val(&setup.env),
+                )),
+                sub_invocations: std::vec![],
+            }
+        )]
+    );
+
+    // The new code keeps the storage and registers itself with the vaults while migrating
+    let market_address = setup.market.address.clone();
+    setup.hedge_vault.set_market(&Address::generate(&setup.env));
+    setup.env.as_contract(&market_address, || {
+        let storage = setup.env.storage().instance();
+        storage.remove(&MarketDataKey::SchemaVersion);
+    });
+    assert_eq!(setup.market.schema_version(), 0);
+    assert_eq!(setup.market.migrate(), 1);
+    assert_eq!(setup.market.schema_version(), 1);
+    assert_eq!(setup.market.admin_address(), setup.admin);
+    assert_eq!(setup.hedge_vault.market_address(), market_address);
+}
+
+#[test]
+fn test_reschedule_event_moves_vault_lock_windows() {
+    let setup = setup();
+    assert_eq!(setup.hedge_vault.lock_timestamp(), EVENT_TIMESTAMP - 600);
+    setup.market.reschedule_event(&(2 * EVENT_TIMESTAMP));
+    assert_eq!(setup.market.expected_time_of_event(), 2 * EVENT_TIMESTAMP);
+    assert_eq!(setup.market.time_of_lock(), 2 * EVENT_TIMESTAMP - 600);
+    for vault in [&setup.hedge_vault, &setup.risk_vault] {
+        assert_eq!(vault.lock_timestamp(), 2 * EVENT_TIMESTAMP - 600);
+        assert_eq!(vault.unlock_timestamp(), 2 * EVENT_TIMESTAMP + 1_200);
+    }
+
+    // Once the vaults are locked for the event it can no longer be moved
+    setup.env.ledger().set_timestamp(2 * EVENT_TIMESTAMP - 600);
+    assert_eq!(
+        setup.market.try_reschedule_event(&(3 * EVENT_TIMESTAMP)),
+        Err(Ok(MarketError::AlreadyLocked))
+    );
+    assert_eq!(
+        setup.market.try_reschedule_event(&0),
+        Err(Ok(MarketError::InvalidUnixTimestamp))
+    );
+}
@@ -5,19 +5,18 @@ use crate::{
     storage::{read_allowance, remove_allowance, write_allowance},
 };
 
-#[derive(Clone)]
+#[derive(Clone, Debug, Eq, PartialEq)]
 #[contracttype]
 pub struct AllowanceData {
-    amount: i128,
-    expiry_ledger: u32,
+    pub amount: i128,
+    pub expiry_ledger: u32,
 }
 
 const DAY_IN_LEDGERS: u32 = 17280; // Assuming 5s per ledger: 24 * 60 * 60 / 5
-const MAXIMUM_DAYS: u32 = 30;
-const MAXIMUM_LEDGERS: u32 = MAXIMUM_DAYS * DAY_IN_LEDGERS; // 30 days maximum
+const MAXIMUM_DAYS: u32 = 30; // Only for approvals given in days
 
 pub(crate) fn _calculate_expiry_ledger(env: &Env, days: u32) -> Result<u32, VaultError> {
-    if days <= 0 || days > MAXIMUM_DAYS {
+    if days == 0 || days > MAXIMUM_DAYS {
         Err(VaultError::InvalidExpiryDays)
     } else {
         let ledgers: u32 = days.checked_mul(DAY_IN_LEDGERS).unwrap();
@@ -31,10 +30,9 @@ fn _get_min_expiry_ledger(env: &Env) -> u32 {
 }
 
 fn _get_max_expiry_ledger(env: &Env) -> u32 {
-    env.ledger()
-        .sequence()
-        .checked_add(MAXIMUM_LEDGERS)
-        .unwrap()
+    // Allowances live in temporary storage, so like the Stellar asset contract they may last
+    // as long as the network keeps an entry alive
+    env.ledger().max_live_until_ledger()
 }
 
 pub(crate) fn _approve_allowance(
@@ -45,6 +43,13 @@ pub(crate) fn _approve_allowance(
     expiry_ledger: u32,
 ) -> Result<(), VaultError> {
     // Assume that owner is already authorized here
+    if amount == 0 {
+        // Zero amount revokes the allowance, expiry is irrelevant in this case
+        remove_allowance(env, owner.clone(), spender.clone());
+        _emit_approval_event(env, owner, spender, amount, expiry_ledger);
+        return Ok(());
+    }
+
     let min_expiry: u32 = _get_min_expiry_ledger(env);
     let max_expiry: u32 = _get_max_expiry_ledger(env);
 
@@ -63,6 +68,65 @@ pub(crate) fn _approve_allowance(
     }
 }
 
+pub(crate) fn _read_allowance_amount(env: &Env, owner: &Address, spender: &Address) -> i128 {
+    _read_live_allowance(env, owner, spender).map_or(0, |allowance| allowance.amount)
+}
+
+pub(crate) fn _read_live_allowance(
+    env: &Env,
+    owner: &Address,
+    spender: &Address,
+) -> Option<AllowanceData> {
+    read_allowance(env, owner.clone(), spender.clone())
+        .filter(|allowance| env.ledger().sequence() <= allowance.expiry_ledger)
+}
+
+pub(crate) fn _increase_allowance(
+    env: &Env,
+    owner: &Address,
+    spender: &Address,
+    amount: i128,
+) -> Result<i128, VaultError> {
+    // Assume that owner is already authorized here, the expiry of the allowance is kept
+    let allowance: AllowanceData =
+        _read_live_allowance(env, owner, spender).ok_or(VaultError::NoAllowance)?;
+    let new_allowance_amount: i128 = allowance
+        .amount
+        .checked_add(amount)
+        .ok_or(VaultError::InvalidAmount)?;
+    _approve_allowance(
+        env,
+        owner,
+        spender,
+        new_allowance_amount,
+        allowance.expiry_ledger,
+    )?;
+    Ok(new_allowance_amount)
+}
+
+pub(crate) fn _decrease_allowance(
+    env: &Env,
+    owner: &Address,
+    spender: &Address,
+    amount: i128,
+) -> Result<i128, VaultError> {
+    // Assume that owner is already authorized here, decreasing to zero revokes the allowance
+    let allowance: AllowanceData =
+        _read_live_allowance(env, owner, spender).ok_or(VaultError::NoAllowance)?;
+    if amount > allowance.amount {
+        return Err(VaultError::InsufficientAllowance);
+    }
+    let new_allowance_amount: i128 = allowance.amount - amount;
+    _approve_allowance(
+        env,
+        owner,
+        spender,
+        new_allowance_amount,
+        allowance.expiry_ledger,
+    )?;
+    Ok(new_allowance_amount)
+}
+
 pub(crate) fn _spend_allowance(
     env: &Env,
     owner: &Address,
@@ -18,6 +18,31 @@ pub enum VaultError {
     InvalidExpiryDays = 12,
     AdministratorError = 13,
     CannotApproveOrTransferToSelf = 14,
+    BaseUnitMigrationRequired = 15,
+    MarketNotSet = 16,
+    InsufficientManagedAssets = 17,
+    UnbackedSettlement = 18,
+    DepositBelowMinimum = 19,
+    ExceededTotalAssetsCap = 20,
+    ExceededAccountCap = 21,
+    Paused = 22,
+    DepositPaused = 23,
+    WithdrawPaused = 24,
+    Locked = 25,
+    InsufficientAssetBalance = 26,
+    InsufficientVaultLiquidity = 27,
+    NotAllowlisted = 28,
+    Unauthorized = 29,
+    AccountFrozen = 30,
+    InsufficientShareBalance = 31,
+    NothingToClaim = 32,
+    DeadlineExpired = 33,
+    InsufficientSharesOut = 34,
+    ExcessiveAssetsIn = 35,
+    ExcessiveSharesBurned = 36,
+    InsufficientAssetsOut = 37,
+    PermitSignerNotSet = 38,
+    ArithmeticError = 39,
 }
 
 #[contracterror]
@@ -34,4 +59,22 @@ pub enum ContractError {
     WithdrawIsAlreadyNotPaused = 8,
     InvalidLockTimes = 9,
     ArithmeticError = 10,
+    AlreadyMigrated = 11,
+    IncompleteHolderList = 12,
+    InvalidDecimalsOffset = 13,
+    InvalidSeedAmount = 14,
+    MarketNotSet = 15,
+    InvalidDepositLimits = 16,
+    InvalidFee = 17,
+    AccountIsAlreadyFrozen = 18,
+    AccountIsAlreadyNotFrozen = 19,
+    Unauthorized = 20,
+    InvalidRole = 21,
+    RoleIsAlreadyGranted = 22,
+    RoleIsAlreadyNotGranted = 23,
+    NoPendingAdmin = 24,
+    LockWindowOverlap = 25,
+    TooManyLockWindows = 26,
+    LockWindowNotFound = 27,
+    LockWindowStarted = 28,
 }
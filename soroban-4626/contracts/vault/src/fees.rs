@@ -0,0 +1,93 @@
+use crate::math::{mul_div, safe_add_i128, Rounding};
+
+// https://github.com/OpenZeppelin/openzeppelin-contracts/blob/master/contracts/mocks/docs/ERC4626Fees.sol
+pub(crate) const BASIS_POINT_SCALE: i128 = 10_000;
+pub(crate) const MAX_FEE_BASIS_POINTS: u32 = 1_000; // 10% maximum
+
+// Fee to add to an amount that does not include fees yet, rounded in favour of the vault
+pub(crate) fn _fee_on_raw(assets: i128, fee_basis_points: u32) -> i128 {
+    mul_div(
+        assets,
+        fee_basis_points as i128,
+        BASIS_POINT_SCALE,
+        Rounding::Ceil,
+    )
+}
+
+// Fee part of an amount that already includes fees, rounded in favour of the vault
+pub(crate) fn _fee_on_total(assets: i128, fee_basis_points: u32) -> i128 {
+    mul_div(
+        assets,
+        fee_basis_points as i128,
+        fee_basis_points as i128 + BASIS_POINT_SCALE,
+        Rounding::Ceil,
+    )
+}
+
+pub(crate) const SECONDS_PER_YEAR: u64 = 31_536_000;
+pub(crate) const PRICE_SCALE: i128 = 1_000_000_000_000_000_000; // 1e18
+pub(crate) const MAX_MANAGEMENT_FEE_BASIS_POINTS: u32 = 500; // 5% per year maximum
+pub(crate) const MAX_PERFORMANCE_FEE_BASIS_POINTS: u32 = 5_000; // 50% of profit maximum
+
+// Time-based fee on the managed assets, pro rata over a 365-day year
+pub(crate) fn _management_fee(total_assets: i128, fee_basis_points: u32, elapsed: u64) -> i128 {
+    mul_div(
+        total_assets,
+        fee_basis_points as i128 * elapsed as i128,
+        BASIS_POINT_SCALE * SECONDS_PER_YEAR as i128,
+        Rounding::Floor,
+    )
+}
+
+// Assets per share scaled by PRICE_SCALE, using the same virtual shares and assets as the conversions
+pub(crate) fn _share_price(total_assets: i128, virtual_total_shares: i128) -> i128 {
+    mul_div(
+        safe_add_i128(total_assets, 1),
+        PRICE_SCALE,
+        virtual_total_shares,
+        Rounding::Floor,
+    )
+}
+
+// Fee on the profit made since the share price last stood at the high-water mark
+pub(crate) fn _performance_fee(
+    share_price: i128,
+    high_water_mark: i128,
+    virtual_total_shares: i128,
+    fee_basis_points: u32,
+) -> i128 {
+    if share_price <= high_water_mark {
+        0
+    } else {
+        let profit: i128 = mul_div(
+            share_price - high_water_mark,
+            virtual_total_shares,
+            PRICE_SCALE,
+            Rounding::Floor,
+        );
+        mul_div(
+            profit,
+            fee_basis_points as i128,
+            BASIS_POINT_SCALE,
+            Rounding::Floor,
+        )
+    }
+}
+
+// Shares to mint so that they are worth the fee once minted, diluting the existing holders
+pub(crate) fn _fee_shares(
+    fee_assets: i128,
+    total_assets: i128,
+    virtual_total_shares: i128,
+) -> i128 {
+    if fee_assets <= 0 || fee_assets >= total_assets {
+        0
+    } else {
+        mul_div(
+            fee_assets,
+            virtual_total_shares,
+            safe_add_i128(total_assets - fee_assets, 1),
+            Rounding::Floor,
+        )
+    }
+}
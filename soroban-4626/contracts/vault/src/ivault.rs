@@ -1,6 +1,11 @@
-use soroban_sdk::{Address, Env, String};
+use soroban_sdk::{Address, BytesN, Env, String, Vec};
 
-use crate::errors::{ContractError, VaultError};
+use crate::{
+    allowance::AllowanceData,
+    errors::{ContractError, VaultError},
+    keys::{DepositLimits, FeeConfig, LockWindow},
+    roles::Role,
+};
 
 pub trait IPublicVault {
     fn initialize(
@@ -9,8 +14,14 @@ pub trait IPublicVault {
         asset_address: Address,
         lock_timestamp: u64,
         unlock_timestamp: u64,
+        decimals_offset: u32,
+        seed_assets: Option<i128>,
     ) -> Result<(String, String, u32), ContractError>;
     fn administrator_address(env: &Env) -> Result<Address, ContractError>;
+    fn pending_administrator(env: &Env) -> Result<Option<Address>, ContractError>;
+    fn propose_administrator(env: Env, new_admin: Address) -> Result<bool, ContractError>;
+    fn accept_administrator(env: Env) -> Result<bool, ContractError>;
+    fn cancel_administrator_transfer(env: Env) -> Result<bool, ContractError>;
     fn asset_decimals(env: &Env) -> Result<u32, ContractError>;
     fn asset_symbol(env: &Env) -> Result<String, ContractError>;
     fn asset_name(env: &Env) -> Result<String, ContractError>;
@@ -19,24 +30,43 @@ pub trait IPublicVault {
     fn total_assets(env: &Env) -> Result<i128, ContractError>;
     fn total_shares(env: &Env) -> Result<i128, ContractError>;
     fn balance_of_shares(env: &Env, address: Address) -> Result<i128, ContractError>;
+    fn holder_count(env: &Env) -> Result<u32, ContractError>;
+    fn holders(env: &Env, start: u32, limit: u32) -> Result<Vec<(Address, i128)>, ContractError>;
+    fn register_holders(env: Env, holders: Vec<Address>) -> Result<u32, ContractError>;
     fn lock_timestamp(env: Env) -> Result<u64, ContractError>;
     fn unlock_timestamp(env: Env) -> Result<u64, ContractError>;
+    fn lock_windows(env: &Env) -> Result<Vec<LockWindow>, ContractError>;
+    fn current_lock_window(env: &Env) -> Result<Option<LockWindow>, ContractError>;
+    fn next_lock_window(env: &Env) -> Result<Option<LockWindow>, ContractError>;
+    fn add_lock_window(
+        env: Env,
+        caller: Address,
+        lock_timestamp: u64,
+        unlock_timestamp: u64,
+    ) -> Result<u32, ContractError>;
+    fn reschedule_lock_window(
+        env: Env,
+        caller: Address,
+        current_lock_timestamp: u64,
+        lock_timestamp: u64,
+        unlock_timestamp: u64,
+    ) -> Result<bool, ContractError>;
     fn convert_to_shares(env: &Env, assets: i128) -> Result<i128, ContractError>;
     fn convert_to_assets(env: &Env, shares: i128) -> Result<i128, ContractError>;
     fn convert_to_shares_simulate(
-        _env: &Env,
+        env: &Env,
         assets: i128,
         total_assets: i128,
         total_shares: i128,
     ) -> Result<i128, ContractError>;
     fn convert_to_assets_simulate(
-        _env: &Env,
+        env: &Env,
         shares: i128,
         total_shares: i128,
         total_assets: i128,
     ) -> Result<i128, ContractError>;
-    fn max_deposit(_: &Env, _address: Address) -> i128;
-    fn max_mint(_: &Env, _address: Address) -> i128;
+    fn max_deposit(env: &Env, receiver: Address) -> i128;
+    fn max_mint(env: &Env, receiver: Address) -> i128;
     fn max_withdraw(env: &Env, owner: Address) -> i128;
     fn max_redeem(env: &Env, owner: Address) -> i128;
     fn preview_deposit(env: &Env, assets: i128) -> Result<i128, ContractError>;
@@ -65,6 +95,64 @@ pub trait IPublicVault {
         receiver: Address,
         owner: Address,
     ) -> Result<i128, VaultError>;
+    fn deposit_min_shares(
+        env: Env,
+        assets: i128,
+        caller: Address,
+        receiver: Address,
+        min_shares: i128,
+        deadline: u64,
+    ) -> Result<i128, VaultError>;
+    fn mint_max_assets(
+        env: Env,
+        shares: i128,
+        caller: Address,
+        receiver: Address,
+        max_assets: i128,
+        deadline: u64,
+    ) -> Result<i128, VaultError>;
+    fn withdraw_max_shares(
+        env: Env,
+        assets: i128,
+        caller: Address,
+        receiver: Address,
+        owner: Address,
+        max_shares: i128,
+        deadline: u64,
+    ) -> Result<i128, VaultError>;
+    fn redeem_min_assets(
+        env: Env,
+        shares: i128,
+        caller: Address,
+        receiver: Address,
+        owner: Address,
+        min_assets: i128,
+        deadline: u64,
+    ) -> Result<i128, VaultError>;
+    fn pending_redeem_request(env: &Env, controller: Address) -> i128;
+    fn claimable_redeem_request(env: &Env, controller: Address) -> i128;
+    fn escrowed_shares(env: &Env) -> i128;
+    fn request_redeem(
+        env: Env,
+        shares: i128,
+        caller: Address,
+        controller: Address,
+        owner: Address,
+    ) -> Result<i128, VaultError>;
+    fn claim(env: Env, controller: Address, receiver: Address) -> Result<i128, VaultError>;
+    fn claim_by(
+        env: Env,
+        caller: Address,
+        controller: Address,
+        receiver: Address,
+    ) -> Result<i128, VaultError>;
+    fn is_operator(env: Env, owner: Address, operator: Address) -> bool;
+    fn set_operator(
+        env: Env,
+        owner: Address,
+        operator: Address,
+        approved: bool,
+    ) -> Result<bool, VaultError>;
     fn approve_shares(
         env: Env,
         owner: Address,
@@ -72,12 +160,49 @@ pub trait IPublicVault {
         approve_amount: i128,
         expire_in_days: u32,
     ) -> Result<bool, VaultError>;
+    fn allowance_details(env: Env, owner: Address, spender: Address) -> Option<AllowanceData>;
+    fn increase_allowance(
+        env: Env,
+        owner: Address,
+        spender: Address,
+        amount: i128,
+    ) -> Result<i128, VaultError>;
+    fn decrease_allowance(
+        env: Env,
+        owner: Address,
+        spender: Address,
+        amount: i128,
+    ) -> Result<i128, VaultError>;
+    fn revoke_allowance(env: Env, owner: Address, spender: Address) -> Result<bool, VaultError>;
+    fn permit_signer(env: Env, owner: Address) -> Option<BytesN<32>>;
+    fn set_permit_signer(
+        env: Env,
+        owner: Address,
+        public_key: Option<BytesN<32>>,
+    ) -> Result<bool, VaultError>;
+    fn nonces(env: Env, owner: Address) -> u64;
+    fn permit(
+        env: Env,
+        owner: Address,
+        spender: Address,
+        amount: i128,
+        expiration_ledger: u32,
+        deadline: u64,
+        signature: BytesN<64>,
+    ) -> Result<bool, VaultError>;
     fn transfer_shares(
         env: Env,
         owner: Address,
         receiver: Address,
         shares_amount: i128,
     ) -> Result<bool, VaultError>;
+    fn transfer_shares_from(
+        env: Env,
+        spender: Address,
+        owner: Address,
+        receiver: Address,
+        shares_amount: i128,
+    ) -> Result<bool, VaultError>;
     fn approve_asset_allowance(
         env: Env,
         asset_address: Address,
@@ -87,10 +212,97 @@ pub trait IPublicVault {
     ) -> Result<(), VaultError>;
     fn is_paused(env: Env) -> bool;
     fn pause(env: Env) -> Result<bool, ContractError>;
+    fn pause_by(env: Env, caller: Address) -> Result<bool, ContractError>;
     fn unpause(env: Env) -> Result<bool, ContractError>;
+    fn unpause_by(env: Env, caller: Address) -> Result<bool, ContractError>;
     fn pause_deposit(env: Env) -> Result<bool, ContractError>;
+    fn pause_deposit_by(env: Env, caller: Address) -> Result<bool, ContractError>;
     fn pause_withdrawal(env: Env) -> Result<bool, ContractError>;
+    fn pause_withdrawal_by(env: Env, caller: Address) -> Result<bool, ContractError>;
     fn unpause_withdrawal(env: Env) -> Result<bool, ContractError>;
+    fn unpause_withdrawal_by(env: Env, caller: Address) -> Result<bool, ContractError>;
     fn unpause_deposit(env: Env) -> Result<bool, ContractError>;
+    fn unpause_deposit_by(env: Env, caller: Address) -> Result<bool, ContractError>;
+    fn is_frozen(env: Env, address: Address) -> bool;
+    fn freeze(env: Env, address: Address) -> Result<bool, ContractError>;
+    fn freeze_by(env: Env, caller: Address, address: Address) -> Result<bool, ContractError>;
+    fn unfreeze(env: Env, address: Address) -> Result<bool, ContractError>;
+    fn unfreeze_by(env: Env, caller: Address, address: Address) -> Result<bool, ContractError>;
     fn extend_vault_ttl(env: &Env) -> Result<bool, ContractError>;
+    fn extend_shares_ttl(env: &Env, address: Address) -> Result<bool, ContractError>;
+    fn upgrade(env: Env, new_wasm_hash: BytesN<32>) -> Result<bool, ContractError>;
+    fn schema_version(env: Env) -> u32;
+    fn migrate(env: Env) -> Result<u32, ContractError>;
+    fn uses_base_units(env: Env) -> bool;
+    fn migrate_to_base_units(env: Env, holders: Vec<Address>) -> Result<i128, ContractError>;
+    fn untracked_assets(env: &Env) -> Result<i128, ContractError>;
+    fn sync(env: Env) -> Result<i128, ContractError>;
+    fn skim(env: Env, to: Address) -> Result<i128, ContractError>;
+    fn market_address(env: &Env) -> Result<Address, ContractError>;
+    fn set_market(env: Env, market: Address) -> Result<bool, ContractError>;
+    fn fees(env: &Env) -> Result<FeeConfig, ContractError>;
+    fn set_fees(
+        env: Env,
+        entry_fee_basis_points: u32,
+        exit_fee_basis_points: u32,
+        recipient: Address,
+    ) -> Result<bool, ContractError>;
+    fn set_fees_by(
+        env: Env,
+        caller: Address,
+        entry_fee_basis_points: u32,
+        exit_fee_basis_points: u32,
+        recipient: Address,
+    ) -> Result<bool, ContractError>;
+    fn set_accrual_fees(
+        env: Env,
+        management_fee_basis_points: u32,
+        performance_fee_basis_points: u32,
+        treasury: Address,
+    ) -> Result<bool, ContractError>;
+    fn set_accrual_fees_by(
+        env: Env,
+        caller: Address,
+        management_fee_basis_points: u32,
+        performance_fee_basis_points: u32,
+        treasury: Address,
+    ) -> Result<bool, ContractError>;
+    fn accrue_fees(env: Env) -> Result<i128, ContractError>;
+    fn pending_fees(env: &Env) -> Result<i128, ContractError>;
+    fn high_water_mark(env: &Env) -> Result<i128, ContractError>;
+    fn compliance_address(env: &Env) -> Result<Option<Address>, ContractError>;
+    fn set_compliance(env: Env, compliance: Option<Address>) -> Result<bool, ContractError>;
+    fn is_allowlist_enabled(env: Env) -> bool;
+    fn set_allowlist_enabled(env: Env, enabled: bool) -> Result<bool, ContractError>;
+    fn is_allowlisted(env: Env, address: Address) -> bool;
+    fn add_to_allowlist(
+        env: Env,
+        caller: Address,
+        addresses: Vec<Address>,
+    ) -> Result<u32, VaultError>;
+    fn remove_from_allowlist(
+        env: Env,
+        caller: Address,
+        addresses: Vec<Address>,
+    ) -> Result<u32, VaultError>;
+    fn settle_out(env: Env, to: Address, amount: i128) -> Result<i128, VaultError>;
+    fn settle_out_by(
+        env: Env,
+        caller: Address,
+        to: Address,
+        amount: i128,
+    ) -> Result<i128, VaultError>;
+    fn settle_in(env: Env, amount: i128) -> Result<i128, VaultError>;
+    fn settle_in_by(env: Env, caller: Address, amount: i128) -> Result<i128, VaultError>;
+    fn has_role(env: Env, role: Role, account: Address) -> bool;
+    fn roles_of(env: Env, account: Address) -> Vec<Role>;
+    fn grant_role(env: Env, role: Role, account: Address) -> Result<bool, ContractError>;
+    fn revoke_role(env: Env, role: Role, account: Address) -> Result<bool, ContractError>;
+    fn deposit_limits(env: &Env) -> Result<DepositLimits, ContractError>;
+    fn set_deposit_limits(
+        env: Env,
+        total_assets_cap: Option<i128>,
+        account_cap: Option<i128>,
+        min_deposit: Option<i128>,
+    ) -> Result<bool, ContractError>;
 }
@@ -1,9 +1,13 @@
 use soroban_sdk::{contracttype, Address};
 
+use crate::roles::Role;
+
 #[derive(Clone)]
 #[contracttype]
 pub enum DataKey {
     AdminAddress,
+    PendingAdminAddress,
+    SchemaVersion,
     AssetAddress,
     AssetName,
     AssetSymbol,
@@ -14,6 +18,85 @@ pub enum DataKey {
     IsPaused,
     DepositPaused,
     WithdrawPaused,
-    LockTimestamp,
-    UnlockTimestamp,
+    Frozen(Address), // (account)
+    LockTimestamp,   // single lock window of schema version 1 and earlier
+    UnlockTimestamp, // single lock window of schema version 1 and earlier
+    LockWindows,
+    BaseUnitAccounting,
+    DecimalsOffset,
+    TotalManagedAssets,
+    MarketAddress,
+    TotalAssetsCap,
+    AccountDepositCap,
+    MinDeposit,
+    EntryFeeBasisPoints,
+    ExitFeeBasisPoints,
+    FeeRecipient,
+    ManagementFeeBasisPoints,
+    PerformanceFeeBasisPoints,
+    Treasury,
+    LastFeeAccrual,
+    HighWaterMark,
+    AllowlistEnabled,
+    Allowlisted(Address), // (account)
+    ComplianceAddress,
+    Role(Role, Address),    // (role, account)
+    RedeemRequest(Address), // (controller)
+    SettlementEpoch,
+    LastSettlement,
+    EscrowedShares,
+    PermitSigner(Address),      // (owner)
+    PermitNonce(Address),       // (owner)
+    Operator(Address, Address), // (owner, operator)
+    HolderCount,
+    HolderAt(u32),        // (position)
+    HolderIndex(Address), // (holder)
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct DepositLimits {
+    pub total_assets_cap: Option<i128>,
+    pub account_cap: Option<i128>,
+    pub min_deposit: Option<i128>,
+}
+
+// Signed off-chain by the owner's permit signer, the signature covers the XDR of this message
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct PermitMessage {
+    pub vault: Address,
+    pub owner: Address,
+    pub spender: Address,
+    pub amount: i128,
+    pub expiration_ledger: u32,
+    pub nonce: u64,
+    pub deadline: u64,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct LockWindow {
+    pub lock_timestamp: u64,
+    pub unlock_timestamp: u64, // inclusive
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct RedeemRequest {
+    pub pending_shares: i128,
+    pub claimable_shares: i128,
+    pub epoch: u32,            // settlement epoch of the latest pending shares
+    pub unlock_timestamp: u64, // end of the lock window the pending shares were requested in
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct FeeConfig {
+    pub entry_fee_basis_points: u32,
+    pub exit_fee_basis_points: u32,
+    pub recipient: Option<Address>,
+    pub management_fee_basis_points: u32,
+    pub performance_fee_basis_points: u32,
+    pub treasury: Option<Address>,
 }
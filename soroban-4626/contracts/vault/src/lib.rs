@@ -2,8 +2,12 @@
 
 mod allowance;
 mod errors;
+mod fees;
 pub mod ivault;
 mod keys;
 mod math;
+pub mod roles;
 mod storage;
 pub mod vault;
+
+mod test;
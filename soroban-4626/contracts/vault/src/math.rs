@@ -40,19 +40,271 @@ pub fn safe_mul(a: i128, b: i128) -> i128 {
         .unwrap()
 }
 
-pub fn safe_div(a: i128, b: i128) -> i128 {
-    a.checked_div(b)
-        .ok_or(ContractError::ArithmeticError)
-        .unwrap()
+// Computes a * b / denominator with a 256-bit intermediate product, so that the
+// multiplication itself never overflows. Only the final quotient must fit into i128.
+pub fn mul_div(a: i128, b: i128, denominator: i128, rounding: Rounding) -> i128 {
+    if denominator == 0 {
+        panic!("Division by zero");
+    }
+    let negative: bool = (a < 0) ^ (b < 0) ^ (denominator < 0);
+    let (high, low) = full_mul(a.unsigned_abs(), b.unsigned_abs());
+    let (quotient, remainder) = full_div_rem(high, low, denominator.unsigned_abs());
+    let round_away_from_zero: bool = remainder != 0
+        && match rounding {
+            Rounding::Floor => negative,
+            Rounding::Ceil => !negative,
+            Rounding::Trunc => false,
+            Rounding::Expand => true,
+        };
+    let magnitude: u128 = if round_away_from_zero {
+        quotient
+            .checked_add(1)
+            .ok_or(ContractError::ArithmeticError)
+            .unwrap()
+    } else {
+        quotient
+    };
+    to_signed(magnitude, negative)
+}
+
+// Multiplies two u128 values into a 256-bit result, returned as (high, low) halves.
+fn full_mul(a: u128, b: u128) -> (u128, u128) {
+    const MASK: u128 = u64::MAX as u128;
+    let (a_high, a_low) = (a >> 64, a & MASK);
+    let (b_high, b_low) = (b >> 64, b & MASK);
+    let low_low: u128 = a_low * b_low;
+    let low_high: u128 = a_low * b_high;
+    let high_low: u128 = a_high * b_low;
+    let high_high: u128 = a_high * b_high;
+    // Sum of three values below 2^64 each, cannot overflow
+    let middle: u128 = (low_low >> 64) + (low_high & MASK) + (high_low & MASK);
+    let low: u128 = (low_low & MASK) | (middle << 64);
+    let high: u128 = high_high + (low_high >> 64) + (high_low >> 64) + (middle >> 64);
+    (high, low)
 }
 
-pub fn mul_div(a: i128, b: i128, denominator: i128, _rounding: Rounding) -> i128 {
-    // TODO: improve
-    if a <= 0 || b <= 0 {
-        0
+// Divides a 256-bit value (high, low) by a u128 divisor, returning (quotient, remainder).
+// Panics when the quotient does not fit into u128.
+fn full_div_rem(high: u128, low: u128, divisor: u128) -> (u128, u128) {
+    if high == 0 {
+        return (low / divisor, low % divisor);
+    }
+    if high >= divisor {
+        panic!("Arithmetic overflow");
+    }
+    // Schoolbook binary long division, the running remainder always stays below divisor
+    let mut quotient: u128 = 0;
+    let mut remainder: u128 = high;
+    for i in (0..128).rev() {
+        let carry: u128 = remainder >> 127;
+        remainder = (remainder << 1) | ((low >> i) & 1);
+        if carry == 1 || remainder >= divisor {
+            remainder = remainder.wrapping_sub(divisor);
+            quotient |= 1 << i;
+        }
+    }
+    (quotient, remainder)
+}
+
+fn to_signed(magnitude: u128, negative: bool) -> i128 {
+    if negative {
+        if magnitude == i128::MIN.unsigned_abs() {
+            i128::MIN
+        } else {
+            let value: i128 = i128::try_from(magnitude)
+                .map_err(|_| ContractError::ArithmeticError)
+                .unwrap();
+            -value
+        }
     } else {
-        let temp = safe_mul(a, b);
-        let result = safe_div(temp, denominator);
-        result
+        i128::try_from(magnitude)
+            .map_err(|_| ContractError::ArithmeticError)
+            .unwrap()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // Reference implementation for products that fit into i128
+    fn reference(a: i128, b: i128, denominator: i128, rounding: Rounding) -> i128 {
+        let product: i128 = a * b;
+        let quotient: i128 = product / denominator;
+        let remainder: i128 = product % denominator;
+        if remainder == 0 {
+            return quotient;
+        }
+        let negative: bool = (remainder < 0) != (denominator < 0);
+        match rounding {
+            Rounding::Floor => quotient - negative as i128,
+            Rounding::Ceil => quotient + !negative as i128,
+            Rounding::Trunc => quotient,
+            Rounding::Expand => quotient + if negative { -1 } else { 1 },
+        }
+    }
+
+    fn all_roundings() -> [Rounding; 4] {
+        [
+            Rounding::Floor,
+            Rounding::Ceil,
+            Rounding::Trunc,
+            Rounding::Expand,
+        ]
+    }
+
+    #[test]
+    fn test_matches_reference_for_small_values() {
+        for a in -25..=25 {
+            for b in -25..=25 {
+                for denominator in -9..=9 {
+                    if denominator == 0 {
+                        continue;
+                    }
+                    for rounding in all_roundings() {
+                        assert_eq!(
+                            mul_div(a, b, denominator, rounding.clone()),
+                            reference(a, b, denominator, rounding),
+                            "a={} b={} denominator={}",
+                            a,
+                            b,
+                            denominator
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_zero_operands() {
+        for rounding in all_roundings() {
+            assert_eq!(mul_div(0, i128::MAX, 1, rounding.clone()), 0);
+            assert_eq!(mul_div(i128::MIN, 0, -1, rounding.clone()), 0);
+            assert_eq!(mul_div(0, 0, i128::MAX, rounding), 0);
+        }
+    }
+
+    #[test]
+    fn test_rounding_of_positive_fraction() {
+        // 7 * 3 / 2 = 10.5
+        assert_eq!(mul_div(7, 3, 2, Rounding::Floor), 10);
+        assert_eq!(mul_div(7, 3, 2, Rounding::Ceil), 11);
+        assert_eq!(mul_div(7, 3, 2, Rounding::Trunc), 10);
+        assert_eq!(mul_div(7, 3, 2, Rounding::Expand), 11);
+    }
+
+    #[test]
+    fn test_rounding_of_negative_fraction() {
+        // -7 * 3 / 2 = -10.5
+        assert_eq!(mul_div(-7, 3, 2, Rounding::Floor), -11);
+        assert_eq!(mul_div(-7, 3, 2, Rounding::Ceil), -10);
+        assert_eq!(mul_div(-7, 3, 2, Rounding::Trunc), -10);
+        assert_eq!(mul_div(-7, 3, 2, Rounding::Expand), -11);
+        // Negative denominator yields the same sign
+        assert_eq!(mul_div(7, 3, -2, Rounding::Floor), -11);
+        assert_eq!(mul_div(-7, -3, -2, Rounding::Ceil), -10);
+    }
+
+    #[test]
+    fn test_exact_division_is_not_rounded() {
+        for rounding in all_roundings() {
+            assert_eq!(mul_div(6, 4, 3, rounding.clone()), 8);
+            assert_eq!(mul_div(-6, 4, 3, rounding), -8);
+        }
+    }
+
+    #[test]
+    fn test_intermediate_product_above_i128() {
+        for rounding in all_roundings() {
+            assert_eq!(
+                mul_div(i128::MAX, i128::MAX, i128::MAX, rounding.clone()),
+                i128::MAX
+            );
+            assert_eq!(
+                mul_div(i128::MIN, i128::MAX, i128::MAX, rounding.clone()),
+                i128::MIN
+            );
+            assert_eq!(
+                mul_div(i128::MIN, i128::MIN, i128::MIN, rounding.clone()),
+                i128::MIN
+            );
+            assert_eq!(mul_div(i128::MAX, 2, 2, rounding.clone()), i128::MAX);
+            assert_eq!(mul_div(i128::MIN, 2, 2, rounding), i128::MIN);
+        }
+    }
+
+    #[test]
+    fn test_large_values_with_remainder() {
+        // (2^127 - 1) * 3 / 2 overflows, but (2^127 - 1) * 2 / 3 does not
+        let expected_floor: i128 = 113427455640312821154458202477256070484;
+        assert_eq!(mul_div(i128::MAX, 2, 3, Rounding::Floor), expected_floor);
+        assert_eq!(mul_div(i128::MAX, 2, 3, Rounding::Ceil), expected_floor + 1);
+        assert_eq!(
+            mul_div(i128::MAX, -2, 3, Rounding::Floor),
+            -expected_floor - 1
+        );
+        assert_eq!(mul_div(i128::MAX, -2, 3, Rounding::Trunc), -expected_floor);
+        // Typical vault values: 10^30 assets, 10^30 shares, 10^30 + 1 denominator
+        let big: i128 = 1_000_000_000_000_000_000_000_000_000_000;
+        assert_eq!(mul_div(big, big, big + 1, Rounding::Floor), big - 1);
+        assert_eq!(mul_div(big, big, big + 1, Rounding::Ceil), big);
+    }
+
+    #[test]
+    fn test_result_at_signed_bounds() {
+        assert_eq!(mul_div(i128::MAX, 1, 1, Rounding::Ceil), i128::MAX);
+        assert_eq!(mul_div(i128::MIN, 1, 1, Rounding::Floor), i128::MIN);
+        assert_eq!(mul_div(i128::MIN + 1, 1, -1, Rounding::Trunc), i128::MAX);
+    }
+
+    #[test]
+    fn test_denominator_of_one_and_minus_one() {
+        for rounding in all_roundings() {
+            assert_eq!(mul_div(12345, 678, 1, rounding.clone()), 12345 * 678);
+            assert_eq!(mul_div(12345, 678, -1, rounding), -12345 * 678);
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_division_by_zero() {
+        mul_div(1, 1, 0, Rounding::Floor);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_positive_overflow() {
+        mul_div(i128::MAX, 2, 1, Rounding::Floor);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_negative_overflow() {
+        mul_div(i128::MIN, 2, 1, Rounding::Floor);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_min_divided_by_minus_one_overflows() {
+        mul_div(i128::MIN, 1, -1, Rounding::Floor);
+    }
+
+    #[test]
+    fn test_rounding_at_upper_bound() {
+        // (2^128 - 1) / 2 = i128::MAX + 0.5
+        let third: i128 = (u128::MAX / 3) as i128;
+        assert_eq!(mul_div(third, 3, 2, Rounding::Floor), i128::MAX);
+        assert_eq!(mul_div(third, 3, 2, Rounding::Trunc), i128::MAX);
+        assert_eq!(mul_div(-third, 3, 2, Rounding::Floor), i128::MIN);
+        assert_eq!(mul_div(-third, 3, 2, Rounding::Expand), i128::MIN);
+        assert_eq!(mul_div(-third, 3, 2, Rounding::Ceil), i128::MIN + 1);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_rounding_up_overflows() {
+        let third: i128 = (u128::MAX / 3) as i128;
+        mul_div(third, 3, 2, Rounding::Ceil);
     }
 }
@@ -0,0 +1,22 @@
+use soroban_sdk::contracttype;
+
+// The owner is the vault administrator, every other role is granted and revoked by the owner
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[contracttype]
+pub enum Role {
+    Owner,          // Administers the vault and can move funds
+    Pauser,         // Can pause and unpause
+    Guardian,       // Can pause in an emergency, but not unpause
+    FeeManager,     // Configures fees
+    MarketOperator, // Settles collateral, i.e. the market contract
+    Compliance,     // Manages the allowlist and frozen accounts
+}
+
+pub const ALL_ROLES: [Role; 6] = [
+    Role::Owner,
+    Role::Pauser,
+    Role::Guardian,
+    Role::FeeManager,
+    Role::MarketOperator,
+    Role::Compliance,
+];
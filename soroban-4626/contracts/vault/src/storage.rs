@@ -1,5 +1,9 @@
-use crate::{allowance::AllowanceData, keys::DataKey};
-use soroban_sdk::{Address, Env, String};
+use crate::{
+    allowance::AllowanceData,
+    keys::{DataKey, LockWindow, RedeemRequest},
+    roles::Role,
+};
+use soroban_sdk::{Address, BytesN, Env, String, Vec};
 
 /*
   Anything stored in instance storage has an archival TTL that is tied to the contract instance itself.
@@ -27,6 +31,32 @@ pub fn write_administrator(e: &Env, address: &Address) {
     e.storage().instance().set(&key, address);
 }
 
+pub fn read_schema_version(e: &Env) -> u32 {
+    // Vaults deployed before schema versioning have no version stored
+    let key = DataKey::SchemaVersion;
+    e.storage().instance().get(&key).unwrap_or(0)
+}
+
+pub fn write_schema_version(e: &Env, version: &u32) {
+    let key = DataKey::SchemaVersion;
+    e.storage().instance().set(&key, version);
+}
+
+pub fn read_pending_administrator(e: &Env) -> Option<Address> {
+    let key = DataKey::PendingAdminAddress;
+    e.storage().instance().get(&key)
+}
+
+pub fn write_pending_administrator(e: &Env, address: &Address) {
+    let key = DataKey::PendingAdminAddress;
+    e.storage().instance().set(&key, address);
+}
+
+pub fn remove_pending_administrator(e: &Env) {
+    let key = DataKey::PendingAdminAddress;
+    e.storage().instance().remove(&key);
+}
+
 pub fn write_asset_address(e: &Env, address: &Address) {
     let key = DataKey::AssetAddress;
     e.storage().instance().set(&key, address);
@@ -53,8 +83,18 @@ pub fn write_total_shares(e: &Env, shares: &i128) {
 }
 
 pub fn write_total_shares_of(e: &Env, adress: Address, shares: &i128) {
+    // Per-holder balances live in persistent storage, so the instance entry does not grow with every depositor
     let key = DataKey::TotalSharesOf(adress);
-    e.storage().instance().set(&key, shares);
+    if *shares == 0 {
+        e.storage().persistent().remove(&key);
+    } else {
+        e.storage().persistent().set(&key, shares);
+        e.storage()
+            .persistent()
+            .extend_ttl(&key, BUMP_THRESHOLD, EXTEND_TO_DAYS);
+    }
+    // Balances written by earlier versions were kept in instance storage
+    e.storage().instance().remove(&key);
 }
 
 pub fn read_asset_decimals(e: &Env) -> u32 {
@@ -84,21 +124,110 @@ pub fn read_total_shares(e: &Env) -> i128 {
 
 pub fn read_total_shares_of(e: &Env, address: Address) -> i128 {
     let key = DataKey::TotalSharesOf(address);
+    match e.storage().persistent().get::<DataKey, i128>(&key) {
+        Some(shares) => {
+            e.storage()
+                .persistent()
+                .extend_ttl(&key, BUMP_THRESHOLD, EXTEND_TO_DAYS);
+            shares
+        }
+        // Fall back to balances written by earlier versions, they are moved on the next write
+        None => e.storage().instance().get(&key).unwrap_or(0),
+    }
+}
+
+pub fn read_holder_count(e: &Env) -> u32 {
+    let key = DataKey::HolderCount;
     e.storage().instance().get(&key).unwrap_or(0)
 }
 
+pub fn write_holder_count(e: &Env, count: &u32) {
+    let key = DataKey::HolderCount;
+    e.storage().instance().set(&key, count);
+}
+
+pub fn read_holder_at(e: &Env, position: u32) -> Address {
+    let key = DataKey::HolderAt(position);
+    let holder: Address = e.storage().persistent().get(&key).unwrap();
+    e.storage()
+        .persistent()
+        .extend_ttl(&key, BUMP_THRESHOLD, EXTEND_TO_DAYS);
+    holder
+}
+
+pub fn write_holder_at(e: &Env, position: u32, holder: &Address) {
+    let key = DataKey::HolderAt(position);
+    e.storage().persistent().set(&key, holder);
+    e.storage()
+        .persistent()
+        .extend_ttl(&key, BUMP_THRESHOLD, EXTEND_TO_DAYS);
+}
+
+pub fn remove_holder_at(e: &Env, position: u32) {
+    let key = DataKey::HolderAt(position);
+    e.storage().persistent().remove(&key);
+}
+
+pub fn read_holder_index(e: &Env, holder: Address) -> Option<u32> {
+    // The position entry is extended together with the index, both must outlive the balance
+    let key = DataKey::HolderIndex(holder);
+    let position: Option<u32> = e.storage().persistent().get(&key);
+    if let Some(position) = position {
+        e.storage()
+            .persistent()
+            .extend_ttl(&key, BUMP_THRESHOLD, EXTEND_TO_DAYS);
+        e.storage().persistent().extend_ttl(
+            &DataKey::HolderAt(position),
+            BUMP_THRESHOLD,
+            EXTEND_TO_DAYS,
+        );
+    }
+    position
+}
+
+pub fn write_holder_index(e: &Env, holder: Address, position: &u32) {
+    let key = DataKey::HolderIndex(holder);
+    e.storage().persistent().set(&key, position);
+    e.storage()
+        .persistent()
+        .extend_ttl(&key, BUMP_THRESHOLD, EXTEND_TO_DAYS);
+}
+
+pub fn remove_holder_index(e: &Env, holder: Address) {
+    let key = DataKey::HolderIndex(holder);
+    e.storage().persistent().remove(&key);
+}
+
 pub fn read_allowance(e: &Env, owner: Address, spender: Address) -> Option<AllowanceData> {
     let key = DataKey::Allowance(owner.clone(), spender.clone());
-    e.storage().instance().get(&key)
+    match e.storage().temporary().get::<DataKey, AllowanceData>(&key) {
+        Some(allowance) => Some(allowance),
+        // Fall back to allowances written by earlier versions, they are moved on the next write
+        None => match e.storage().persistent().get::<DataKey, AllowanceData>(&key) {
+            Some(allowance) => Some(allowance),
+            None => e.storage().instance().get(&key),
+        },
+    }
 }
 
 pub fn write_allowance(e: &Env, owner: Address, spender: Address, allowance: AllowanceData) {
+    // Temporary entries live until the allowance expires, expired allowances stop costing rent
     let key = DataKey::Allowance(owner.clone(), spender.clone());
-    e.storage().instance().set(&key, &allowance);
+    e.storage().temporary().set(&key, &allowance);
+    let live_for: u32 = allowance
+        .expiry_ledger
+        .saturating_sub(e.ledger().sequence());
+    if live_for > 0 {
+        e.storage().temporary().extend_ttl(&key, live_for, live_for);
+    }
+    e.storage().persistent().remove(&key);
+    e.storage().instance().remove(&key);
 }
 
 pub fn remove_allowance(e: &Env, owner: Address, spender: Address) {
     let key = DataKey::Allowance(owner.clone(), spender.clone());
+    e.storage().temporary().remove(&key);
+    e.storage().persistent().remove(&key);
     e.storage().instance().remove(&key);
 }
 
@@ -147,24 +276,425 @@ pub fn remove_withdraw_paused(e: &Env) {
     e.storage().instance().remove(&key);
 }
 
-pub fn read_lock_timestamp(e: &Env) -> u64 {
-    let key = DataKey::LockTimestamp;
+pub fn is_operator(e: &Env, owner: Address, operator: Address) -> bool {
+    let key = DataKey::Operator(owner, operator);
+    let approved: bool = e.storage().persistent().has(&key);
+    if approved {
+        e.storage()
+            .persistent()
+            .extend_ttl(&key, BUMP_THRESHOLD, EXTEND_TO_DAYS);
+    }
+    approved
+}
+
+pub fn write_operator(e: &Env, owner: Address, operator: Address) {
+    let key = DataKey::Operator(owner, operator);
+    e.storage().persistent().set(&key, &());
+    e.storage()
+        .persistent()
+        .extend_ttl(&key, BUMP_THRESHOLD, EXTEND_TO_DAYS);
+}
+
+pub fn remove_operator(e: &Env, owner: Address, operator: Address) {
+    let key = DataKey::Operator(owner, operator);
+    e.storage().persistent().remove(&key);
+}
+
+pub fn read_permit_signer(e: &Env, owner: Address) -> Option<BytesN<32>> {
+    let key = DataKey::PermitSigner(owner);
+    let signer: Option<BytesN<32>> = e.storage().persistent().get(&key);
+    if signer.is_some() {
+        e.storage()
+            .persistent()
+            .extend_ttl(&key, BUMP_THRESHOLD, EXTEND_TO_DAYS);
+    }
+    signer
+}
+
+pub fn write_permit_signer(e: &Env, owner: Address, public_key: &BytesN<32>) {
+    let key = DataKey::PermitSigner(owner);
+    e.storage().persistent().set(&key, public_key);
+    e.storage()
+        .persistent()
+        .extend_ttl(&key, BUMP_THRESHOLD, EXTEND_TO_DAYS);
+}
+
+pub fn remove_permit_signer(e: &Env, owner: Address) {
+    let key = DataKey::PermitSigner(owner);
+    e.storage().persistent().remove(&key);
+}
+
+pub fn read_permit_nonce(e: &Env, owner: Address) -> u64 {
+    let key = DataKey::PermitNonce(owner);
+    let nonce: Option<u64> = e.storage().persistent().get(&key);
+    if nonce.is_some() {
+        e.storage()
+            .persistent()
+            .extend_ttl(&key, BUMP_THRESHOLD, EXTEND_TO_DAYS);
+    }
+    nonce.unwrap_or(0)
+}
+
+pub fn write_permit_nonce(e: &Env, owner: Address, nonce: &u64) {
+    // Nonces only ever grow, so a used signature can never be replayed
+    let key = DataKey::PermitNonce(owner);
+    e.storage().persistent().set(&key, nonce);
+    e.storage()
+        .persistent()
+        .extend_ttl(&key, BUMP_THRESHOLD, EXTEND_TO_DAYS);
+}
+
+pub fn read_redeem_request(e: &Env, controller: Address) -> Option<RedeemRequest> {
+    let key = DataKey::RedeemRequest(controller);
+    let request: Option<RedeemRequest> = e.storage().persistent().get(&key);
+    if request.is_some() {
+        e.storage()
+            .persistent()
+            .extend_ttl(&key, BUMP_THRESHOLD, EXTEND_TO_DAYS);
+    }
+    request
+}
+
+pub fn write_redeem_request(e: &Env, controller: Address, request: &RedeemRequest) {
+    let key = DataKey::RedeemRequest(controller);
+    e.storage().persistent().set(&key, request);
+    e.storage()
+        .persistent()
+        .extend_ttl(&key, BUMP_THRESHOLD, EXTEND_TO_DAYS);
+}
+
+pub fn remove_redeem_request(e: &Env, controller: Address) {
+    let key = DataKey::RedeemRequest(controller);
+    e.storage().persistent().remove(&key);
+}
+
+pub fn read_settlement_epoch(e: &Env) -> u32 {
+    let key = DataKey::SettlementEpoch;
+    e.storage().instance().get(&key).unwrap_or(0)
+}
+
+pub fn write_settlement_epoch(e: &Env, epoch: &u32) {
+    let key = DataKey::SettlementEpoch;
+    e.storage().instance().set(&key, epoch);
+}
+
+pub fn read_last_settlement(e: &Env) -> Option<u64> {
+    let key = DataKey::LastSettlement;
+    e.storage().instance().get(&key)
+}
+
+pub fn write_last_settlement(e: &Env, timestamp: &u64) {
+    let key = DataKey::LastSettlement;
+    e.storage().instance().set(&key, timestamp);
+}
+
+pub fn read_escrowed_shares(e: &Env) -> i128 {
+    let key = DataKey::EscrowedShares;
+    e.storage().instance().get(&key).unwrap_or(0)
+}
+
+pub fn write_escrowed_shares(e: &Env, shares: &i128) {
+    // Kept apart from the vault's own balance, which only ever holds the dead shares
+    let key = DataKey::EscrowedShares;
+    e.storage().instance().set(&key, shares);
+}
+
+pub fn is_frozen(e: &Env, address: Address) -> bool {
+    let key = DataKey::Frozen(address);
+    let frozen: bool = e.storage().persistent().has(&key);
+    if frozen {
+        e.storage()
+            .persistent()
+            .extend_ttl(&key, BUMP_THRESHOLD, EXTEND_TO_DAYS);
+    }
+    frozen
+}
+
+pub fn write_frozen(e: &Env, address: Address) {
+    let key = DataKey::Frozen(address);
+    e.storage().persistent().set(&key, &());
+    e.storage()
+        .persistent()
+        .extend_ttl(&key, BUMP_THRESHOLD, EXTEND_TO_DAYS);
+}
+
+pub fn remove_frozen(e: &Env, address: Address) {
+    let key = DataKey::Frozen(address);
+    e.storage().persistent().remove(&key);
+}
+
+pub fn allowlist_enabled(e: &Env) -> bool {
+    let key = DataKey::AllowlistEnabled;
+    e.storage().instance().has(&key)
+}
+
+pub fn write_allowlist_enabled(e: &Env) {
+    let key = DataKey::AllowlistEnabled;
+    e.storage().instance().set(&key, &())
+}
+
+pub fn remove_allowlist_enabled(e: &Env) {
+    let key = DataKey::AllowlistEnabled;
+    e.storage().instance().remove(&key);
+}
+
+pub fn is_allowlisted(e: &Env, address: Address) -> bool {
+    let key = DataKey::Allowlisted(address);
+    let allowlisted: bool = e.storage().persistent().has(&key);
+    if allowlisted {
+        e.storage()
+            .persistent()
+            .extend_ttl(&key, BUMP_THRESHOLD, EXTEND_TO_DAYS);
+    }
+    allowlisted
+}
+
+pub fn write_allowlisted(e: &Env, address: Address) {
+    let key = DataKey::Allowlisted(address);
+    e.storage().persistent().set(&key, &());
+    e.storage()
+        .persistent()
+        .extend_ttl(&key, BUMP_THRESHOLD, EXTEND_TO_DAYS);
+}
+
+pub fn remove_allowlisted(e: &Env, address: Address) {
+    let key = DataKey::Allowlisted(address);
+    e.storage().persistent().remove(&key);
+}
+
+pub fn read_compliance_address(e: &Env) -> Option<Address> {
+    let key = DataKey::ComplianceAddress;
+    e.storage().instance().get(&key)
+}
+
+pub fn write_compliance_address(e: &Env, address: &Option<Address>) {
+    let key = DataKey::ComplianceAddress;
+    match address {
+        Some(value) => e.storage().instance().set(&key, value),
+        None => e.storage().instance().remove(&key),
+    }
+}
+
+pub fn has_role(e: &Env, role: Role, address: Address) -> bool {
+    let key = DataKey::Role(role, address);
+    let granted: bool = e.storage().persistent().has(&key);
+    if granted {
+        e.storage()
+            .persistent()
+            .extend_ttl(&key, BUMP_THRESHOLD, EXTEND_TO_DAYS);
+    }
+    granted
+}
+
+pub fn write_role(e: &Env, role: Role, address: Address) {
+    let key = DataKey::Role(role, address);
+    e.storage().persistent().set(&key, &());
+    e.storage()
+        .persistent()
+        .extend_ttl(&key, BUMP_THRESHOLD, EXTEND_TO_DAYS);
+}
+
+pub fn remove_role(e: &Env, role: Role, address: Address) {
+    let key = DataKey::Role(role, address);
+    e.storage().persistent().remove(&key);
+}
+
+pub fn has_lock_windows(e: &Env) -> bool {
+    let key = DataKey::LockWindows;
+    e.storage().instance().has(&key)
+}
+
+pub fn read_lock_windows(e: &Env) -> Vec<LockWindow> {
+    let key = DataKey::LockWindows;
     e.storage().instance().get(&key).unwrap()
 }
 
-pub fn write_lock_timestamp(e: &Env, time: &u64) {
-    let key = DataKey::LockTimestamp;
-    e.storage().instance().set(&key, time);
+pub fn write_lock_windows(e: &Env, windows: &Vec<LockWindow>) {
+    let key = DataKey::LockWindows;
+    e.storage().instance().set(&key, windows);
+}
+
+pub fn read_legacy_lock_window(e: &Env) -> Option<LockWindow> {
+    // Vaults of schema version 1 and earlier store a single lock window as two timestamps
+    let lock_timestamp: Option<u64> = e.storage().instance().get(&DataKey::LockTimestamp);
+    let unlock_timestamp: Option<u64> = e.storage().instance().get(&DataKey::UnlockTimestamp);
+    match (lock_timestamp, unlock_timestamp) {
+        (Some(lock_timestamp), Some(unlock_timestamp)) => Some(LockWindow {
+            lock_timestamp,
+            unlock_timestamp,
+        }),
+        _ => None,
+    }
+}
+
+pub fn remove_legacy_lock_window(e: &Env) {
+    e.storage().instance().remove(&DataKey::LockTimestamp);
+    e.storage().instance().remove(&DataKey::UnlockTimestamp);
+}
+
+pub fn has_base_unit_accounting(e: &Env) -> bool {
+    let key = DataKey::BaseUnitAccounting;
+    e.storage().instance().has(&key)
+}
+
+pub fn write_base_unit_accounting(e: &Env) {
+    let key = DataKey::BaseUnitAccounting;
+    e.storage().instance().set(&key, &())
+}
+
+pub fn read_decimals_offset(e: &Env) -> u32 {
+    // Vaults initialized before the offset was configurable used no offset
+    let key = DataKey::DecimalsOffset;
+    e.storage().instance().get(&key).unwrap_or(0)
+}
+
+pub fn write_decimals_offset(e: &Env, offset: &u32) {
+    let key = DataKey::DecimalsOffset;
+    e.storage().instance().set(&key, offset);
+}
+
+pub fn has_total_managed_assets(e: &Env) -> bool {
+    let key = DataKey::TotalManagedAssets;
+    e.storage().instance().has(&key)
 }
 
-pub fn read_unlock_timestamp(e: &Env) -> u64 {
-    let key = DataKey::UnlockTimestamp;
+pub fn read_total_managed_assets(e: &Env) -> i128 {
+    let key = DataKey::TotalManagedAssets;
     e.storage().instance().get(&key).unwrap()
 }
 
-pub fn write_unlock_timestamp(e: &Env, time: &u64) {
-    let key = DataKey::UnlockTimestamp;
-    e.storage().instance().set(&key, time);
+pub fn write_total_managed_assets(e: &Env, assets: &i128) {
+    let key = DataKey::TotalManagedAssets;
+    e.storage().instance().set(&key, assets);
+}
+
+pub fn read_market_address(e: &Env) -> Option<Address> {
+    let key = DataKey::MarketAddress;
+    e.storage().instance().get(&key)
+}
+
+pub fn write_market_address(e: &Env, address: &Address) {
+    let key = DataKey::MarketAddress;
+    e.storage().instance().set(&key, address);
+}
+
+pub fn read_total_assets_cap(e: &Env) -> Option<i128> {
+    let key = DataKey::TotalAssetsCap;
+    e.storage().instance().get(&key)
+}
+
+pub fn write_total_assets_cap(e: &Env, cap: &Option<i128>) {
+    let key = DataKey::TotalAssetsCap;
+    match cap {
+        Some(value) => e.storage().instance().set(&key, value),
+        None => e.storage().instance().remove(&key),
+    }
+}
+
+pub fn read_account_deposit_cap(e: &Env) -> Option<i128> {
+    let key = DataKey::AccountDepositCap;
+    e.storage().instance().get(&key)
+}
+
+pub fn write_account_deposit_cap(e: &Env, cap: &Option<i128>) {
+    let key = DataKey::AccountDepositCap;
+    match cap {
+        Some(value) => e.storage().instance().set(&key, value),
+        None => e.storage().instance().remove(&key),
+    }
+}
+
+pub fn read_min_deposit(e: &Env) -> Option<i128> {
+    let key = DataKey::MinDeposit;
+    e.storage().instance().get(&key)
+}
+
+pub fn write_min_deposit(e: &Env, min: &Option<i128>) {
+    let key = DataKey::MinDeposit;
+    match min {
+        Some(value) => e.storage().instance().set(&key, value),
+        None => e.storage().instance().remove(&key),
+    }
+}
+
+pub fn read_entry_fee_basis_points(e: &Env) -> u32 {
+    let key = DataKey::EntryFeeBasisPoints;
+    e.storage().instance().get(&key).unwrap_or(0)
+}
+
+pub fn write_entry_fee_basis_points(e: &Env, basis_points: &u32) {
+    let key = DataKey::EntryFeeBasisPoints;
+    e.storage().instance().set(&key, basis_points);
+}
+
+pub fn read_exit_fee_basis_points(e: &Env) -> u32 {
+    let key = DataKey::ExitFeeBasisPoints;
+    e.storage().instance().get(&key).unwrap_or(0)
+}
+
+pub fn write_exit_fee_basis_points(e: &Env, basis_points: &u32) {
+    let key = DataKey::ExitFeeBasisPoints;
+    e.storage().instance().set(&key, basis_points);
+}
+
+pub fn read_fee_recipient(e: &Env) -> Option<Address> {
+    let key = DataKey::FeeRecipient;
+    e.storage().instance().get(&key)
+}
+
+pub fn write_fee_recipient(e: &Env, recipient: &Address) {
+    let key = DataKey::FeeRecipient;
+    e.storage().instance().set(&key, recipient);
+}
+
+pub fn read_management_fee_basis_points(e: &Env) -> u32 {
+    let key = DataKey::ManagementFeeBasisPoints;
+    e.storage().instance().get(&key).unwrap_or(0)
+}
+
+pub fn write_management_fee_basis_points(e: &Env, basis_points: &u32) {
+    let key = DataKey::ManagementFeeBasisPoints;
+    e.storage().instance().set(&key, basis_points);
+}
+
+pub fn read_performance_fee_basis_points(e: &Env) -> u32 {
+    let key = DataKey::PerformanceFeeBasisPoints;
+    e.storage().instance().get(&key).unwrap_or(0)
+}
+
+pub fn write_performance_fee_basis_points(e: &Env, basis_points: &u32) {
+    let key = DataKey::PerformanceFeeBasisPoints;
+    e.storage().instance().set(&key, basis_points);
+}
+
+pub fn read_treasury(e: &Env) -> Option<Address> {
+    let key = DataKey::Treasury;
+    e.storage().instance().get(&key)
+}
+
+pub fn write_treasury(e: &Env, treasury: &Address) {
+    let key = DataKey::Treasury;
+    e.storage().instance().set(&key, treasury);
+}
+
+pub fn read_last_fee_accrual(e: &Env) -> Option<u64> {
+    let key = DataKey::LastFeeAccrual;
+    e.storage().instance().get(&key)
+}
+
+pub fn write_last_fee_accrual(e: &Env, timestamp: &u64) {
+    let key = DataKey::LastFeeAccrual;
+    e.storage().instance().set(&key, timestamp);
+}
+
+pub fn read_high_water_mark(e: &Env) -> Option<i128> {
+    let key = DataKey::HighWaterMark;
+    e.storage().instance().get(&key)
+}
+
+pub fn write_high_water_mark(e: &Env, share_price: &i128) {
+    let key = DataKey::HighWaterMark;
+    e.storage().instance().set(&key, share_price);
 }
 
 /*
@@ -198,16 +728,41 @@ pub fn extend_contract_ttl(env: &Env, threshold: u32, extend_to: u32) {
     env.storage().instance().extend_ttl(threshold, extend_to);
 }
 
-#[allow(dead_code)]
 pub fn extend_persistence_ttl(env: &Env, key: DataKey, threshold: u32, extend_to: u32) {
     env.storage()
         .persistent()
         .extend_ttl(&key, threshold, extend_to);
 }
 
+pub fn extend_shares_of_ttl(env: &Env, address: Address, threshold: u32, extend_to: u32) -> bool {
+    let key = DataKey::TotalSharesOf(address.clone());
+    if env.storage().persistent().has(&key) {
+        extend_persistence_ttl(env, key, threshold, extend_to);
+        extend_holder_ttl(env, address, threshold, extend_to);
+        true
+    } else if let Some(shares) = env.storage().instance().get::<DataKey, i128>(&key) {
+        // Balance written by an earlier version, moving it to persistent storage extends it as well
+        write_total_shares_of(env, address.clone(), &shares);
+        extend_holder_ttl(env, address, threshold, extend_to);
+        true
+    } else {
+        false
+    }
+}
+
+fn extend_holder_ttl(env: &Env, address: Address, threshold: u32, extend_to: u32) {
+    // The registry entries of a holder must live as long as its balance
+    let key = DataKey::HolderIndex(address);
+    if let Some(position) = env.storage().persistent().get::<DataKey, u32>(&key) {
+        extend_persistence_ttl(env, key, threshold, extend_to);
+        extend_persistence_ttl(env, DataKey::HolderAt(position), threshold, extend_to);
+    }
+}
+
 #[allow(dead_code)]
 pub fn extend_persistence_all_ttl(_env: &Env, _threshold: u32, _extend_to: u32) {
     // call extend_persistence_ttl (above) with persistence keys one by one
-    // currently no key is stored in persistence, only in instance
+    // per-holder keys (balances and allowances) cannot be enumerated here,
+    // they are extended whenever they are read or written, and via extend_shares_of_ttl
     // .. add more as needed
 }
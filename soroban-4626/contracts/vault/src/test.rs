@@ -0,0 +1,2127 @@
+#![cfg(test)]
+extern crate std;
+
+use ed25519_dalek::SigningKey;
+use soroban_sdk::{
+    symbol_short,
+    testutils::{
+        ed25519::Sign, storage::Persistent, Address as _, AuthorizedFunction, AuthorizedInvocation,
+        Events, Ledger, MockAuth, MockAuthInvoke,
+    },
+    token::{StellarAssetClient, TokenClient},
+    vec, Address, BytesN, Env, IntoVal, InvokeError, String, Symbol, Val, Vec,
+};
+
+use crate::{
+    allowance::AllowanceData,
+    errors::{ContractError, VaultError},
+    keys::{DataKey, DepositLimits, FeeConfig, LockWindow, PermitMessage},
+    roles::Role,
+    vault::{Vault, VaultClient},
+};
+
+const LOCK_TIMESTAMP: u64 = 1_000_000;
+const UNLOCK_TIMESTAMP: u64 = 2_000_000;
+const ONE_TOKEN: i128 = 10_000_000; // 7 decimals, as for Stellar assets
+
+struct Setup<'a> {
+    env: Env,
+    admin: Address,
+    asset: TokenClient<'a>,
+    asset_admin: StellarAssetClient<'a>,
+    vault: VaultClient<'a>,
+}
+
+fn setup<'a>(decimals_offset: u32, seed_assets: Option<i128>) -> Setup<'a> {
+    let env = Env::default();
+    env.mock_all_auths();
+    let admin = Address::generate(&env);
+    let asset_address = env
+        .register_stellar_asset_contract_v2(admin.clone())
+        .address();
+    let asset = TokenClient::new(&env, &asset_address);
+    let asset_admin = StellarAssetClient::new(&env, &asset_address);
+    asset_admin.mint(&admin, &(1_000 * ONE_TOKEN));
+    let vault_address = env.register_contract(None, Vault);
+    let vault = VaultClient::new(&env, &vault_address);
+    vault.initialize(
+        &admin,
+        &asset_address,
+        &LOCK_TIMESTAMP,
+        &UNLOCK_TIMESTAMP,
+        &decimals_offset,
+        &seed_assets,
+    );
+    Setup {
+        env,
+        admin,
+        asset,
+        asset_admin,
+        vault,
+    }
+}
+
+fn funded_user(setup: &Setup, amount: i128) -> Address {
+    let user = Address::generate(&setup.env);
+    setup.asset_admin.mint(&user, &amount);
+    user
+}
+
+// The attacker deposits the smallest possible amount, then donates a large balance directly to the vault,
+// which is meant to round the victim's shares down and let the attacker capture part of the victim's deposit.
+// Returns the assets received by the victim and the attacker when both exit.
+fn run_donation_attack(setup: &Setup, donation: i128, victim_deposit: i128) -> (i128, i128) {
+    let attacker = funded_user(setup, 1 + donation);
+    let victim = funded_user(setup, victim_deposit);
+
+    let attacker_shares = setup.vault.deposit(&1, &attacker, &attacker);
+    setup
+        .asset
+        .transfer(&attacker, &setup.vault.contract_address(), &donation);
+    // Direct transfers are not counted until the admin syncs them, which models a recognized inflow
+    setup.vault.sync();
+    let victim_shares = setup.vault.deposit(&victim_deposit, &victim, &victim);
+
+    let victim_assets = setup
+        .vault
+        .redeem(&victim_shares, &victim, &victim, &victim);
+    let attacker_assets = setup
+        .vault
+        .redeem(&attacker_shares, &attacker, &attacker, &attacker);
+    (victim_assets, attacker_assets)
+}
+
+#[test]
+fn test_share_decimals_include_offset() {
+    let setup = setup(6, None);
+    assert_eq!(setup.vault.decimals(), 7 + 6);
+    assert_eq!(setup.vault.asset_decimals(), 7 + 6);
+}
+
+#[test]
+fn test_initialize_rejects_too_large_offset() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let admin = Address::generate(&env);
+    let asset_address = env
+        .register_stellar_asset_contract_v2(admin.clone())
+        .address();
+    let vault = VaultClient::new(&env, &env.register_contract(None, Vault));
+    let result = vault.try_initialize(
+        &admin,
+        &asset_address,
+        &LOCK_TIMESTAMP,
+        &UNLOCK_TIMESTAMP,
+        &19,
+        &None,
+    );
+    assert_eq!(result, Err(Ok(ContractError::InvalidDecimalsOffset)));
+}
+
+#[test]
+fn test_first_deposit_mints_virtual_share_multiple() {
+    let setup = setup(6, None);
+    let user = funded_user(&setup, ONE_TOKEN);
+    let shares = setup.vault.deposit(&ONE_TOKEN, &user, &user);
+    assert_eq!(shares, ONE_TOKEN * 1_000_000);
+    assert_eq!(setup.vault.preview_redeem(&shares), ONE_TOKEN);
+}
+
+#[test]
+fn test_migrate_to_base_units() {
+    let setup = setup(0, None);
+    let alice = funded_user(&setup, 10 * ONE_TOKEN);
+    let bob = funded_user(&setup, 10 * ONE_TOKEN);
+    setup.vault.deposit(&(6 * ONE_TOKEN), &alice, &alice);
+    setup.vault.deposit(&(4 * ONE_TOKEN), &bob, &bob);
+
+    // Vaults deployed before base unit accounting minted one share per whole token
+    setup.env.as_contract(&setup.vault.address, || {
+        let persistent = setup.env.storage().persistent();
+        persistent.set(&DataKey::TotalSharesOf(alice.clone()), &6_i128);
+        persistent.set(&DataKey::TotalSharesOf(bob.clone()), &4_i128);
+        let instance = setup.env.storage().instance();
+        instance.set(&DataKey::TotalShares, &10_i128);
+        instance.remove(&DataKey::BaseUnitAccounting);
+    });
+    assert_eq!(
+        setup.vault.try_deposit(&ONE_TOKEN, &alice, &alice),
+        Err(Ok(VaultError::BaseUnitMigrationRequired))
+    );
+    assert_eq!(
+        setup.vault.try_mint(&ONE_TOKEN, &alice, &alice),
+        Err(Ok(VaultError::BaseUnitMigrationRequired))
+    );
+    assert_eq!(
+        setup.vault.try_withdraw(&ONE_TOKEN, &alice, &alice, &alice),
+        Err(Ok(VaultError::BaseUnitMigrationRequired))
+    );
+    assert_eq!(
+        setup.vault.try_redeem(&1, &alice, &alice, &alice),
+        Err(Ok(VaultError::BaseUnitMigrationRequired))
+    );
+    assert_eq!(
+        setup.vault.try_request_redeem(&1, &alice, &alice, &alice),
+        Err(Ok(VaultError::BaseUnitMigrationRequired))
+    );
+
+    let expiration_ledger = setup.env.ledger().sequence() + 1_000;
+    setup.vault.approve(&alice, &bob, &2, &expiration_ledger);
+
+    assert_eq!(
+        setup
+            .vault
+            .try_migrate_to_base_units(&vec![&setup.env, alice.clone()]),
+        Err(Ok(ContractError::IncompleteHolderList))
+    );
+    // Listing a holder twice does not scale the balance twice
+    let holders = vec![&setup.env, alice.clone(), bob.clone(), alice.clone()];
+    assert_eq!(setup.vault.migrate_to_base_units(&holders), 10 * ONE_TOKEN);
+    assert_eq!(setup.vault.balance(&alice), 6 * ONE_TOKEN);
+    assert_eq!(setup.vault.balance(&bob), 4 * ONE_TOKEN);
+    assert_eq!(setup.vault.preview_redeem(&(6 * ONE_TOKEN)), 6 * ONE_TOKEN);
+    // Allowances are not scaled, the owner approves the spender again
+    assert_eq!(setup.vault.allowance(&alice, &bob), 2);
+    assert_eq!(
+        setup.vault.try_migrate_to_base_units(&holders),
+        Err(Ok(ContractError::AlreadyMigrated))
+    );
+
+    assert_eq!(setup.vault.deposit(&ONE_TOKEN, &alice, &alice), ONE_TOKEN);
+    assert_eq!(
+        setup.vault.redeem(&(4 * ONE_TOKEN), &bob, &bob, &bob),
+        4 * ONE_TOKEN
+    );
+}
+
+fn token_error(error: VaultError) -> soroban_sdk::Error {
+    error.into()
+}
+
+fn last_event(env: &Env) -> Vec<(Address, Vec<Val>, Val)> {
+    let events = env.events().all();
+    events.slice(events.len() - 1..)
+}
+
+#[test]
+fn test_token_interface_metadata() {
+    let setup = setup(6, None);
+    assert_eq!(setup.vault.decimals(), 13);
+
+    // Share name and symbol are the asset's prefixed with "Vault " and "v"
+    setup.env.as_contract(&setup.vault.address, || {
+        let instance = setup.env.storage().instance();
+        instance.set(
+            &DataKey::AssetName,
+            &String::from_str(&setup.env, "USD Coin"),
+        );
+        instance.set(&DataKey::AssetSymbol, &String::from_str(&setup.env, "USDC"));
+    });
+    assert_eq!(
+        setup.vault.name(),
+        String::from_str(&setup.env, "Vault USD Coin")
+    );
+    assert_eq!(setup.vault.symbol(), String::from_str(&setup.env, "vUSDC"));
+
+    // Names that do not fit are cut without splitting a character
+    let long_name = std::format!("a{}", "é".repeat(70));
+    setup.env.as_contract(&setup.vault.address, || {
+        let instance = setup.env.storage().instance();
+        instance.set(
+            &DataKey::AssetName,
+            &String::from_str(&setup.env, &long_name),
+        );
+    });
+    let expected = std::format!("Vault a{}", "é".repeat(60));
+    assert_eq!(expected.len(), 127);
+    assert_eq!(setup.vault.name(), String::from_str(&setup.env, &expected));
+}
+
+#[test]
+fn test_token_interface_transfer_and_burn() {
+    let setup = setup(0, None);
+    let alice = funded_user(&setup, 10 * ONE_TOKEN);
+    let bob = Address::generate(&setup.env);
+    setup.vault.deposit(&(10 * ONE_TOKEN), &alice, &alice);
+
+    setup.vault.transfer(&alice, &bob, &(3 * ONE_TOKEN));
+    assert_eq!(
+        last_event(&setup.env),
+        vec![
+            &setup.env,
+            (
+                setup.vault.address.clone(),
+                (symbol_short!("transfer"), alice.clone(), bob.clone()).into_val(&setup.env),
+                (3 * ONE_TOKEN).into_val(&setup.env),
+            ),
+        ]
+    );
+    assert_eq!(setup.vault.balance(&alice), 7 * ONE_TOKEN);
+    assert_eq!(setup.vault.balance(&bob), 3 * ONE_TOKEN);
+    assert_eq!(
+        setup.vault.try_transfer(&bob, &alice, &(4 * ONE_TOKEN)),
+        Err(Ok(token_error(VaultError::InvalidAmount)))
+    );
+
+    setup.vault.burn(&bob, &ONE_TOKEN);
+    assert_eq!(
+        last_event(&setup.env),
+        vec![
+            &setup.env,
+            (
+                setup.vault.address.clone(),
+                (symbol_short!("burn"), bob.clone()).into_val(&setup.env),
+                ONE_TOKEN.into_val(&setup.env),
+            ),
+        ]
+    );
+    assert_eq!(setup.vault.balance(&bob), 2 * ONE_TOKEN);
+    assert_eq!(setup.vault.total_shares(), 9 * ONE_TOKEN);
+    // The burnt shares' assets stay with the remaining holders
+    assert_eq!(setup.vault.total_assets(), 10 * ONE_TOKEN);
+}
+
+#[test]
+fn test_token_interface_allowances() {
+    let setup = setup(0, None);
+    let alice = funded_user(&setup, 10 * ONE_TOKEN);
+    let spender = Address::generate(&setup.env);
+    let bob = Address::generate(&setup.env);
+    setup.vault.deposit(&(10 * ONE_TOKEN), &alice, &alice);
+
+    assert_eq!(setup.vault.allowance(&alice, &spender), 0);
+    assert_eq!(
+        setup
+            .vault
+            .try_transfer_from(&spender, &alice, &bob, &ONE_TOKEN),
+        Err(Ok(token_error(VaultError::NoAllowance)))
+    );
+    assert_eq!(
+        setup.vault.try_approve(&alice, &alice, &ONE_TOKEN, &100),
+        Err(Ok(token_error(VaultError::CannotApproveOrTransferToSelf)))
+    );
+    assert_eq!(
+        setup.vault.try_approve(&alice, &spender, &ONE_TOKEN, &0),
+        Err(Ok(token_error(VaultError::InvalidExpiry)))
+    );
+
+    // Unlike approve_shares, the expiry is only bounded by the lifetime of a ledger entry
+    let expiration_ledger = setup.env.ledger().sequence() + 60 * 17_280;
+    setup
+        .vault
+        .approve(&alice, &spender, &(4 * ONE_TOKEN), &expiration_ledger);
+    assert_eq!(
+        last_event(&setup.env),
+        vec![
+            &setup.env,
+            (
+                setup.vault.address.clone(),
+                (symbol_short!("approve"), alice.clone(), spender.clone()).into_val(&setup.env),
+                (4 * ONE_TOKEN, expiration_ledger).into_val(&setup.env),
+            ),
+        ]
+    );
+    assert_eq!(setup.vault.allowance(&alice, &spender), 4 * ONE_TOKEN);
+    let beyond_lifetime = setup.env.ledger().max_live_until_ledger() + 1;
+    assert_eq!(
+        setup
+            .vault
+            .try_approve(&alice, &spender, &ONE_TOKEN, &beyond_lifetime),
+        Err(Ok(token_error(VaultError::InvalidExpiry)))
+    );
+
+    setup
+        .vault
+        .transfer_from(&spender, &alice, &bob, &(3 * ONE_TOKEN));
+    assert_eq!(
+        last_event(&setup.env),
+        vec![
+            &setup.env,
+            (
+                setup.vault.address.clone(),
+                (symbol_short!("transfer"), alice.clone(), bob.clone()).into_val(&setup.env),
+                (3 * ONE_TOKEN).into_val(&setup.env),
+            ),
+        ]
+    );
+    assert_eq!(setup.vault.allowance(&alice, &spender), ONE_TOKEN);
+    assert_eq!(setup.vault.balance(&bob), 3 * ONE_TOKEN);
+    assert_eq!(
+        setup
+            .vault
+            .try_burn_from(&spender, &alice, &(2 * ONE_TOKEN)),
+        Err(Ok(token_error(VaultError::InsufficientAllowance)))
+    );
+
+    setup.vault.burn_from(&spender, &alice, &ONE_TOKEN);
+    assert_eq!(setup.vault.allowance(&alice, &spender), 0);
+    assert_eq!(setup.vault.balance(&alice), 6 * ONE_TOKEN);
+    assert_eq!(setup.vault.total_shares(), 9 * ONE_TOKEN);
+
+    // Approving zero revokes the allowance
+    setup
+        .vault
+        .approve(&alice, &spender, &ONE_TOKEN, &expiration_ledger);
+    setup.vault.approve(&alice, &spender, &0, &0);
+    assert_eq!(setup.vault.allowance(&alice, &spender), 0);
+}
+
+#[test]
+fn test_transfer_shares_from_spends_allowance() {
+    let setup = setup(0, None);
+    let owner = funded_user(&setup, 10 * ONE_TOKEN);
+    let spender = Address::generate(&setup.env);
+    let receiver = Address::generate(&setup.env);
+    setup.vault.deposit(&(10 * ONE_TOKEN), &owner, &owner);
+
+    assert_eq!(
+        setup
+            .vault
+            .try_transfer_shares_from(&spender, &owner, &receiver, &ONE_TOKEN),
+        Err(Ok(VaultError::NoAllowance))
+    );
+    setup
+        .vault
+        .approve_shares(&owner, &spender, &(5 * ONE_TOKEN), &1);
+    assert_eq!(
+        setup
+            .vault
+            .try_transfer_shares_from(&spender, &owner, &receiver, &(6 * ONE_TOKEN)),
+        Err(Ok(VaultError::InsufficientAllowance))
+    );
+
+    setup
+        .vault
+        .transfer_shares_from(&spender, &owner, &receiver, &(2 * ONE_TOKEN));
+    assert_eq!(
+        last_event(&setup.env),
+        vec![
+            &setup.env,
+            (
+                setup.vault.address.clone(),
+                (symbol_short!("transfer"), owner.clone(), receiver.clone()).into_val(&setup.env),
+                (2 * ONE_TOKEN).into_val(&setup.env),
+            ),
+        ]
+    );
+    assert_eq!(setup.vault.allowance(&owner, &spender), 3 * ONE_TOKEN);
+    assert_eq!(setup.vault.balance(&owner), 8 * ONE_TOKEN);
+    assert_eq!(setup.vault.balance(&receiver), 2 * ONE_TOKEN);
+
+    // A frozen spender cannot use its allowance
+    setup.vault.freeze(&spender);
+    assert_eq!(
+        setup
+            .vault
+            .try_transfer_shares_from(&spender, &owner, &receiver, &ONE_TOKEN),
+        Err(Ok(VaultError::AccountFrozen))
+    );
+    setup.vault.unfreeze(&spender);
+
+    // The allowance lapses a day after approval, its temporary entry is gone with it
+    setup
+        .env
+        .ledger()
+        .with_mut(|ledger| ledger.sequence_number += 17_280 + 1);
+    assert_eq!(setup.vault.allowance(&owner, &spender), 0);
+    assert_eq!(
+        setup
+            .vault
+            .try_transfer_shares_from(&spender, &owner, &receiver, &ONE_TOKEN),
+        Err(Ok(VaultError::NoAllowance))
+    );
+    assert_eq!(setup.vault.balance(&owner), 8 * ONE_TOKEN);
+}
+
+#[test]
+fn test_balances_live_in_persistent_storage() {
+    let setup = setup(0, None);
+    let user = funded_user(&setup, 10 * ONE_TOKEN);
+    let other = Address::generate(&setup.env);
+    setup.vault.deposit(&(10 * ONE_TOKEN), &user, &user);
+    let key = DataKey::TotalSharesOf(user.clone());
+    let month_in_ledgers: u32 = 30 * 17_280;
+
+    setup.env.as_contract(&setup.vault.address, || {
+        let storage = setup.env.storage();
+        assert!(storage.persistent().has(&key));
+        assert!(!storage.instance().has(&key));
+        assert_eq!(storage.persistent().get_ttl(&key), month_in_ledgers);
+    });
+
+    // Anyone can keep a balance alive once its time-to-live falls below the threshold
+    setup.vault.extend_vault_ttl();
+    setup
+        .env
+        .ledger()
+        .with_mut(|ledger| ledger.sequence_number += 2 * 17_280);
+    assert!(setup.vault.extend_shares_ttl(&user));
+    assert!(!setup.vault.extend_shares_ttl(&other));
+    setup.env.as_contract(&setup.vault.address, || {
+        let ttl = setup.env.storage().persistent().get_ttl(&key);
+        assert_eq!(ttl, month_in_ledgers);
+    });
+
+    // Balances written by earlier versions are read from instance storage
+    setup.env.as_contract(&setup.vault.address, || {
+        let storage = setup.env.storage();
+        storage.persistent().remove(&key);
+        storage.instance().set(&key, &(10 * ONE_TOKEN));
+    });
+    assert_eq!(setup.vault.balance(&user), 10 * ONE_TOKEN);
+
+    // and move to persistent storage on the next write
+    setup.vault.transfer(&user, &other, &ONE_TOKEN);
+    setup.env.as_contract(&setup.vault.address, || {
+        let storage = setup.env.storage();
+        assert!(!storage.instance().has(&key));
+        assert_eq!(storage.persistent().get(&key), Some(9 * ONE_TOKEN));
+    });
+    assert_eq!(setup.vault.balance(&other), ONE_TOKEN);
+
+    // or when their time-to-live is extended
+    setup.env.as_contract(&setup.vault.address, || {
+        let storage = setup.env.storage();
+        storage.persistent().remove(&key);
+        storage.instance().set(&key, &(9 * ONE_TOKEN));
+    });
+    assert!(setup.vault.extend_shares_ttl(&user));
+    setup.env.as_contract(&setup.vault.address, || {
+        let storage = setup.env.storage();
+        assert!(!storage.instance().has(&key));
+        assert_eq!(storage.persistent().get(&key), Some(9 * ONE_TOKEN));
+    });
+}
+
+#[test]
+fn test_donation_rounding_victim_to_zero_shares_is_rejected() {
+    let setup = setup(0, None);
+    let attacker = funded_user(&setup, 1 + 100 * ONE_TOKEN);
+    let victim = funded_user(&setup, 50 * ONE_TOKEN);
+
+    setup.vault.deposit(&1, &attacker, &attacker);
+    setup.asset.transfer(
+        &attacker,
+        &setup.vault.contract_address(),
+        &(100 * ONE_TOKEN),
+    );
+    setup.vault.sync();
+
+    let result = setup.vault.try_deposit(&(50 * ONE_TOKEN), &victim, &victim);
+    assert_eq!(result, Err(Ok(VaultError::ZeroShares)));
+    assert_eq!(setup.asset.balance(&victim), 50 * ONE_TOKEN);
+}
+
+#[test]
+fn test_donation_attack_without_offset_costs_victim() {
+    let setup = setup(0, None);
+    let victim_deposit = 150 * ONE_TOKEN;
+    let (victim_assets, _) = run_donation_attack(&setup, 100 * ONE_TOKEN, victim_deposit);
+    // The victim is rounded down to 2 shares instead of 3 and loses a sixth of the deposit
+    assert!(victim_assets < victim_deposit * 9 / 10);
+}
+
+#[test]
+fn test_donation_attack_mitigated_by_decimals_offset() {
+    let setup = setup(6, None);
+    let donation = 100 * ONE_TOKEN;
+    let victim_deposit = 150 * ONE_TOKEN;
+    let (victim_assets, attacker_assets) = run_donation_attack(&setup, donation, victim_deposit);
+    // The victim loses at most a negligible rounding amount
+    assert!(victim_assets >= victim_deposit - victim_deposit / 100_000);
+    // About half of the donation is captured by the virtual shares, the attack is a loss for the attacker
+    assert!(attacker_assets < donation * 6 / 10);
+}
+
+#[test]
+fn test_donation_attack_mitigated_by_dead_shares() {
+    let seed = 1_000;
+    let setup = setup(0, Some(seed));
+    let vault_address = setup.vault.contract_address();
+    assert_eq!(setup.vault.balance(&vault_address), seed);
+    assert_eq!(setup.vault.total_shares(), seed);
+
+    let donation = 100 * ONE_TOKEN;
+    let victim_deposit = 150 * ONE_TOKEN;
+    let (victim_assets, attacker_assets) = run_donation_attack(&setup, donation, victim_deposit);
+    assert!(victim_assets >= victim_deposit - victim_deposit / 1_000);
+    assert!(attacker_assets < donation / 100);
+    // Dead shares remain locked in the vault
+    assert_eq!(setup.vault.balance(&vault_address), seed);
+    assert_eq!(setup.asset.balance(&setup.admin), 1_000 * ONE_TOKEN - seed);
+}
+
+#[test]
+fn test_direct_transfer_does_not_change_total_assets() {
+    let setup = setup(0, None);
+    let user = funded_user(&setup, 10 * ONE_TOKEN);
+    setup.vault.deposit(&(10 * ONE_TOKEN), &user, &user);
+
+    let donor = funded_user(&setup, 5 * ONE_TOKEN);
+    setup
+        .asset
+        .transfer(&donor, &setup.vault.contract_address(), &(5 * ONE_TOKEN));
+
+    assert_eq!(setup.vault.total_assets(), 10 * ONE_TOKEN);
+    assert_eq!(setup.vault.untracked_assets(), 5 * ONE_TOKEN);
+    assert_eq!(
+        setup.vault.preview_redeem(&(10 * ONE_TOKEN)),
+        10 * ONE_TOKEN
+    );
+}
+
+#[test]
+fn test_settlement_updates_tracked_assets() {
+    let setup = setup(0, None);
+    let user = funded_user(&setup, 10 * ONE_TOKEN);
+    let market = Address::generate(&setup.env);
+    let vault_address = setup.vault.contract_address();
+    setup.vault.deposit(&(10 * ONE_TOKEN), &user, &user);
+    setup.vault.set_market(&market);
+
+    assert_eq!(
+        setup.vault.try_settle_out(&market, &(11 * ONE_TOKEN)),
+        Err(Ok(VaultError::InsufficientManagedAssets))
+    );
+    assert_eq!(
+        setup.vault.settle_out(&market, &(4 * ONE_TOKEN)),
+        6 * ONE_TOKEN
+    );
+    assert_eq!(setup.asset.balance(&market), 4 * ONE_TOKEN);
+    assert_eq!(setup.vault.total_assets(), 6 * ONE_TOKEN);
+    assert_eq!(setup.vault.untracked_assets(), 0);
+
+    // Collateral sent in by the market counts once the market settles it
+    setup
+        .asset
+        .transfer(&market, &vault_address, &(3 * ONE_TOKEN));
+    assert_eq!(setup.vault.total_assets(), 6 * ONE_TOKEN);
+    assert_eq!(setup.vault.untracked_assets(), 3 * ONE_TOKEN);
+    assert_eq!(
+        setup.vault.try_settle_in(&(4 * ONE_TOKEN)),
+        Err(Ok(VaultError::UnbackedSettlement))
+    );
+    assert_eq!(setup.vault.settle_in(&(3 * ONE_TOKEN)), 9 * ONE_TOKEN);
+    assert_eq!(setup.vault.total_assets(), 9 * ONE_TOKEN);
+    assert_eq!(setup.vault.untracked_assets(), 0);
+    assert_eq!(setup.vault.preview_redeem(&(10 * ONE_TOKEN)), 9 * ONE_TOKEN);
+}
+
+#[test]
+fn test_sync_and_skim_untracked_assets() {
+    let setup = setup(0, None);
+    let user = funded_user(&setup, 10 * ONE_TOKEN);
+    setup.vault.deposit(&(10 * ONE_TOKEN), &user, &user);
+    let donor = funded_user(&setup, 6 * ONE_TOKEN);
+    let vault_address = setup.vault.contract_address();
+
+    setup
+        .asset
+        .transfer(&donor, &vault_address, &(2 * ONE_TOKEN));
+    assert_eq!(setup.vault.sync(), 12 * ONE_TOKEN);
+    assert_eq!(setup.vault.total_assets(), 12 * ONE_TOKEN);
+    assert_eq!(setup.vault.untracked_assets(), 0);
+
+    setup
+        .asset
+        .transfer(&donor, &vault_address, &(4 * ONE_TOKEN));
+    let recipient = Address::generate(&setup.env);
+    assert_eq!(setup.vault.skim(&recipient), 4 * ONE_TOKEN);
+    assert_eq!(setup.asset.balance(&recipient), 4 * ONE_TOKEN);
+    assert_eq!(setup.vault.total_assets(), 12 * ONE_TOKEN);
+    assert_eq!(setup.asset.balance(&vault_address), 12 * ONE_TOKEN);
+}
+
+#[test]
+fn test_max_deposit_unlimited_by_default() {
+    let setup = setup(0, None);
+    let user = Address::generate(&setup.env);
+    assert_eq!(setup.vault.max_deposit(&user), i128::MAX);
+    assert_eq!(setup.vault.max_mint(&user), i128::MAX);
+}
+
+#[test]
+fn test_deposit_limits_reflected_in_max_views() {
+    let setup = setup(0, None);
+    setup.vault.set_deposit_limits(
+        &Some(100 * ONE_TOKEN),
+        &Some(30 * ONE_TOKEN),
+        &Some(ONE_TOKEN),
+    );
+    assert_eq!(
+        setup.vault.deposit_limits(),
+        DepositLimits {
+            total_assets_cap: Some(100 * ONE_TOKEN),
+            account_cap: Some(30 * ONE_TOKEN),
+            min_deposit: Some(ONE_TOKEN),
+        }
+    );
+
+    let user = funded_user(&setup, 100 * ONE_TOKEN);
+    assert_eq!(setup.vault.max_deposit(&user), 30 * ONE_TOKEN);
+    setup.vault.deposit(&(25 * ONE_TOKEN), &user, &user);
+    assert_eq!(setup.vault.max_deposit(&user), 5 * ONE_TOKEN);
+    assert_eq!(setup.vault.max_mint(&user), 5 * ONE_TOKEN);
+
+    // Room left below the minimum deposit size cannot be used
+    setup
+        .vault
+        .deposit(&(4 * ONE_TOKEN + ONE_TOKEN / 2), &user, &user);
+    assert_eq!(setup.vault.max_deposit(&user), 0);
+    assert_eq!(setup.vault.max_mint(&user), 0);
+}
+
+#[test]
+fn test_deposit_limits_report_distinct_errors() {
+    let setup = setup(0, None);
+    setup.vault.set_deposit_limits(
+        &Some(50 * ONE_TOKEN),
+        &Some(30 * ONE_TOKEN),
+        &Some(ONE_TOKEN),
+    );
+    let first = funded_user(&setup, 100 * ONE_TOKEN);
+    let second = funded_user(&setup, 100 * ONE_TOKEN);
+
+    let result = setup.vault.try_deposit(&(ONE_TOKEN - 1), &first, &first);
+    assert_eq!(result, Err(Ok(VaultError::DepositBelowMinimum)));
+
+    let result = setup
+        .vault
+        .try_deposit(&(30 * ONE_TOKEN + 1), &first, &first);
+    assert_eq!(result, Err(Ok(VaultError::ExceededAccountCap)));
+    let result = setup.vault.try_mint(&(30 * ONE_TOKEN + 1), &first, &first);
+    assert_eq!(result, Err(Ok(VaultError::ExceededAccountCap)));
+
+    setup.vault.deposit(&(30 * ONE_TOKEN), &first, &first);
+    setup.vault.deposit(&(15 * ONE_TOKEN), &second, &second);
+    let result = setup.vault.try_deposit(&(10 * ONE_TOKEN), &second, &second);
+    assert_eq!(result, Err(Ok(VaultError::ExceededTotalAssetsCap)));
+    let result = setup.vault.try_mint(&(10 * ONE_TOKEN), &second, &second);
+    assert_eq!(result, Err(Ok(VaultError::ExceededTotalAssetsCap)));
+
+    // Removing the limits lifts the caps again
+    setup.vault.set_deposit_limits(&None, &None, &None);
+    setup.vault.deposit(&(10 * ONE_TOKEN), &second, &second);
+    assert_eq!(setup.vault.total_assets(), 55 * ONE_TOKEN);
+}
+
+#[test]
+fn test_set_deposit_limits_rejects_invalid_values() {
+    let setup = setup(0, None);
+    let result = setup.vault.try_set_deposit_limits(&None, &None, &Some(0));
+    assert_eq!(result, Err(Ok(ContractError::InvalidDepositLimits)));
+    let result = setup
+        .vault
+        .try_set_deposit_limits(&Some(ONE_TOKEN), &None, &Some(2 * ONE_TOKEN));
+    assert_eq!(result, Err(Ok(ContractError::InvalidDepositLimits)));
+    let result = setup.vault.try_set_deposit_limits(&None, &Some(-1), &None);
+    assert_eq!(result, Err(Ok(ContractError::InvalidDepositLimits)));
+}
+
+#[test]
+fn test_max_views_are_zero_while_paused_or_locked() {
+    let setup = setup(0, None);
+    let user = funded_user(&setup, 10 * ONE_TOKEN);
+    setup.vault.deposit(&(10 * ONE_TOKEN), &user, &user);
+    assert_eq!(setup.vault.max_withdraw(&user), 10 * ONE_TOKEN);
+    assert_eq!(setup.vault.max_redeem(&user), 10 * ONE_TOKEN);
+
+    setup.vault.pause_deposit();
+    assert_eq!(setup.vault.max_deposit(&user), 0);
+    assert_eq!(setup.vault.max_mint(&user), 0);
+    assert_eq!(setup.vault.max_redeem(&user), 10 * ONE_TOKEN);
+
+    setup.vault.pause();
+    assert_eq!(setup.vault.max_deposit(&user), 0);
+    assert_eq!(setup.vault.max_mint(&user), 0);
+    assert_eq!(setup.vault.max_withdraw(&user), 0);
+    assert_eq!(setup.vault.max_redeem(&user), 0);
+    setup.vault.unpause();
+
+    setup.vault.pause_withdrawal();
+    assert_eq!(setup.vault.max_deposit(&user), i128::MAX);
+    assert_eq!(setup.vault.max_withdraw(&user), 0);
+    assert_eq!(setup.vault.max_redeem(&user), 0);
+    setup.vault.pause();
+    setup.vault.unpause();
+
+    setup.env.ledger().set_timestamp(LOCK_TIMESTAMP);
+    assert_eq!(setup.vault.max_deposit(&user), 0);
+    assert_eq!(setup.vault.max_mint(&user), 0);
+    assert_eq!(setup.vault.max_withdraw(&user), 0);
+    assert_eq!(setup.vault.max_redeem(&user), 0);
+
+    setup.env.ledger().set_timestamp(UNLOCK_TIMESTAMP + 1);
+    assert_eq!(setup.vault.max_redeem(&user), 10 * ONE_TOKEN);
+}
+
+#[test]
+fn test_max_views_are_zero_before_initialize() {
+    let env = Env::default();
+    let vault = VaultClient::new(&env, &env.register_contract(None, Vault));
+    let user = Address::generate(&env);
+    assert_eq!(vault.max_deposit(&user), 0);
+    assert_eq!(vault.max_mint(&user), 0);
+    assert_eq!(vault.max_withdraw(&user), 0);
+    assert_eq!(vault.max_redeem(&user), 0);
+}
+
+#[test]
+fn test_paused_and_locked_vault_returns_typed_errors() {
+    let setup = setup(0, None);
+    let user = funded_user(&setup, 10 * ONE_TOKEN);
+    setup.vault.deposit(&(5 * ONE_TOKEN), &user, &user);
+
+    setup.vault.pause_deposit();
+    let result = setup.vault.try_deposit(&ONE_TOKEN, &user, &user);
+    assert_eq!(result, Err(Ok(VaultError::DepositPaused)));
+    let result = setup.vault.try_mint(&ONE_TOKEN, &user, &user);
+    assert_eq!(result, Err(Ok(VaultError::DepositPaused)));
+    setup.vault.pause();
+    setup.vault.unpause();
+
+    setup.vault.pause_withdrawal();
+    let result = setup.vault.try_withdraw(&ONE_TOKEN, &user, &user, &user);
+    assert_eq!(result, Err(Ok(VaultError::WithdrawPaused)));
+    let result = setup.vault.try_redeem(&ONE_TOKEN, &user, &user, &user);
+    assert_eq!(result, Err(Ok(VaultError::WithdrawPaused)));
+
+    setup.vault.pause();
+    let result = setup.vault.try_deposit(&ONE_TOKEN, &user, &user);
+    assert_eq!(result, Err(Ok(VaultError::Paused)));
+    let result = setup.vault.try_redeem(&ONE_TOKEN, &user, &user, &user);
+    assert_eq!(result, Err(Ok(VaultError::Paused)));
+    setup.vault.unpause();
+
+    setup.env.ledger().set_timestamp(LOCK_TIMESTAMP);
+    let result = setup.vault.try_deposit(&ONE_TOKEN, &user, &user);
+    assert_eq!(result, Err(Ok(VaultError::Locked)));
+    let result = setup.vault.try_withdraw(&ONE_TOKEN, &user, &user, &user);
+    assert_eq!(result, Err(Ok(VaultError::Locked)));
+}
+
+#[test]
+fn test_deposit_above_caller_balance_returns_typed_error() {
+    let setup = setup(0, None);
+    let user = funded_user(&setup, ONE_TOKEN);
+    let result = setup.vault.try_deposit(&(2 * ONE_TOKEN), &user, &user);
+    assert_eq!(result, Err(Ok(VaultError::InsufficientAssetBalance)));
+    assert_eq!(setup.vault.total_shares(), 0);
+}
+
+#[test]
+fn test_entry_and_exit_fees_in_previews_and_transfers() {
+    let setup = setup(0, None);
+    let recipient = Address::generate(&setup.env);
+    setup.vault.set_fees(&100, &200, &recipient);
+    assert_eq!(
+        setup.vault.fees(),
+        FeeConfig {
+            entry_fee_basis_points: 100,
+            exit_fee_basis_points: 200,
+            recipient: Some(recipient.clone()),
+            management_fee_basis_points: 0,
+            performance_fee_basis_points: 0,
+            treasury: None,
+        }
+    );
+
+    let user = funded_user(&setup, 101 * ONE_TOKEN);
+    assert_eq!(
+        setup.vault.preview_deposit(&(101 * ONE_TOKEN)),
+        100 * ONE_TOKEN
+    );
+    assert_eq!(
+        setup.vault.preview_mint(&(100 * ONE_TOKEN)),
+        101 * ONE_TOKEN
+    );
+    let shares = setup.vault.deposit(&(101 * ONE_TOKEN), &user, &user);
+    assert_eq!(shares, 100 * ONE_TOKEN);
+    assert_eq!(setup.asset.balance(&recipient), ONE_TOKEN);
+    assert_eq!(setup.vault.total_assets(), 100 * ONE_TOKEN);
+
+    // 2% on top of the withdrawn assets, so redeeming 100 tokens worth of shares pays out 100 / 1.02
+    let expected_assets = 100 * ONE_TOKEN - (100 * ONE_TOKEN * 200 + 10_199) / 10_200;
+    assert_eq!(setup.vault.preview_redeem(&shares), expected_assets);
+    assert_eq!(setup.vault.max_withdraw(&user), expected_assets);
+    assert!(setup.vault.preview_withdraw(&expected_assets) <= shares);
+
+    let assets = setup.vault.redeem(&shares, &user, &user, &user);
+    assert_eq!(assets, expected_assets);
+    assert_eq!(setup.asset.balance(&user), expected_assets);
+    assert_eq!(
+        setup.asset.balance(&recipient),
+        ONE_TOKEN + 100 * ONE_TOKEN - expected_assets
+    );
+    assert_eq!(setup.vault.total_assets(), 0);
+}
+
+#[test]
+fn test_withdraw_charges_exit_fee_on_top() {
+    let setup = setup(0, None);
+    let recipient = Address::generate(&setup.env);
+    setup.vault.set_fees(&0, &100, &recipient);
+    let user = funded_user(&setup, 100 * ONE_TOKEN);
+    setup.vault.deposit(&(100 * ONE_TOKEN), &user, &user);
+
+    let shares = setup.vault.withdraw(&(50 * ONE_TOKEN), &user, &user, &user);
+    assert_eq!(shares, 50 * ONE_TOKEN + ONE_TOKEN / 2);
+    assert_eq!(setup.asset.balance(&user), 50 * ONE_TOKEN);
+    assert_eq!(setup.asset.balance(&recipient), ONE_TOKEN / 2);
+    assert_eq!(setup.vault.total_assets(), 50 * ONE_TOKEN - ONE_TOKEN / 2);
+}
+
+#[test]
+fn test_set_fees_rejects_invalid_values() {
+    let setup = setup(0, None);
+    let recipient = Address::generate(&setup.env);
+    let result = setup.vault.try_set_fees(&1_001, &0, &recipient);
+    assert_eq!(result, Err(Ok(ContractError::InvalidFee)));
+    let result = setup.vault.try_set_fees(&0, &1_001, &recipient);
+    assert_eq!(result, Err(Ok(ContractError::InvalidFee)));
+    let vault_address = setup.vault.contract_address();
+    let result = setup.vault.try_set_fees(&10, &10, &vault_address);
+    assert_eq!(result, Err(Ok(ContractError::InvalidFee)));
+}
+
+#[test]
+fn test_management_fee_accrues_over_time() {
+    let setup = setup(0, None);
+    let treasury = Address::generate(&setup.env);
+    setup.vault.set_accrual_fees(&200, &0, &treasury);
+    let user = funded_user(&setup, 100 * ONE_TOKEN);
+    setup.vault.deposit(&(100 * ONE_TOKEN), &user, &user);
+    assert_eq!(setup.vault.pending_fees(), 0);
+
+    let start = setup.env.ledger().timestamp();
+    setup.env.ledger().set_timestamp(start + 31_536_000);
+    let pending = setup.vault.pending_fees();
+    assert!(pending > 0);
+    // Previews already account for the shares that are about to be minted
+    let user_assets = setup.vault.preview_redeem(&(100 * ONE_TOKEN));
+
+    assert_eq!(setup.vault.accrue_fees(), pending);
+    assert_eq!(setup.vault.balance(&treasury), pending);
+    assert_eq!(setup.vault.pending_fees(), 0);
+    assert_eq!(setup.vault.preview_redeem(&(100 * ONE_TOKEN)), user_assets);
+    // Two percent of a year's assets, give or take rounding
+    let treasury_assets = setup.vault.preview_redeem(&pending);
+    assert!(treasury_assets > 2 * ONE_TOKEN - 10 && treasury_assets <= 2 * ONE_TOKEN);
+}
+
+#[test]
+fn test_share_transfers_and_burns_accrue_fees() {
+    let setup = setup(0, None);
+    let treasury = Address::generate(&setup.env);
+    let spender = Address::generate(&setup.env);
+    let other = Address::generate(&setup.env);
+    setup.vault.set_accrual_fees(&200, &0, &treasury);
+    let user = funded_user(&setup, 100 * ONE_TOKEN);
+    setup.vault.deposit(&(100 * ONE_TOKEN), &user, &user);
+    setup
+        .vault
+        .approve_shares(&user, &spender, &(10 * ONE_TOKEN), &30);
+
+    // Each call charges the fees accrued so far, before balances or the total supply change
+    let mut minted: i128 = 0;
+    let start = setup.env.ledger().timestamp();
+    for step in 1..=6 {
+        setup.env.ledger().set_timestamp(start + step * 86_400);
+        let pending = setup.vault.pending_fees();
+        assert!(pending > 0);
+        match step {
+            1 => {
+                setup.vault.transfer_shares(&user, &other, &ONE_TOKEN);
+            }
+            2 => {
+                setup
+                    .vault
+                    .transfer_shares_from(&spender, &user, &other, &ONE_TOKEN);
+            }
+            3 => setup.vault.transfer(&user, &other, &ONE_TOKEN),
+            4 => setup
+                .vault
+                .transfer_from(&spender, &user, &other, &ONE_TOKEN),
+            5 => setup.vault.burn(&user, &ONE_TOKEN),
+            _ => setup.vault.burn_from(&spender, &user, &ONE_TOKEN),
+        }
+        minted += pending;
+        assert_eq!(setup.vault.pending_fees(), 0);
+        assert_eq!(setup.vault.balance(&treasury), minted);
+    }
+}
+
+#[test]
+fn test_performance_fee_charged_above_high_water_mark_only() {
+    let setup = setup(0, None);
+    let treasury = Address::generate(&setup.env);
+    setup.vault.set_accrual_fees(&0, &1_000, &treasury);
+    let user = funded_user(&setup, 100 * ONE_TOKEN);
+    setup.vault.deposit(&(100 * ONE_TOKEN), &user, &user);
+    let initial_mark = setup.vault.high_water_mark();
+
+    // 10 tokens of profit, a tenth of which goes to the treasury
+    let vault_address = setup.vault.contract_address();
+    setup.asset_admin.mint(&vault_address, &(10 * ONE_TOKEN));
+    setup.vault.sync();
+    setup.vault.accrue_fees();
+    let fee_shares = setup.vault.balance(&treasury);
+    let treasury_assets = setup.vault.preview_redeem(&fee_shares);
+    assert!(treasury_assets > ONE_TOKEN - 10 && treasury_assets <= ONE_TOKEN);
+    let mark = setup.vault.high_water_mark();
+    assert!(mark > initial_mark);
+
+    // A loss and a partial recovery below the mark do not generate performance fees
+    setup.vault.set_market(&setup.admin);
+    setup.vault.settle_out(&setup.admin, &(5 * ONE_TOKEN));
+    setup.vault.accrue_fees();
+    assert_eq!(setup.vault.high_water_mark(), mark);
+    setup
+        .asset
+        .transfer(&setup.admin, &vault_address, &(2 * ONE_TOKEN));
+    setup.vault.settle_in(&(2 * ONE_TOKEN));
+    assert_eq!(setup.vault.pending_fees(), 0);
+    setup.vault.accrue_fees();
+    assert_eq!(setup.vault.balance(&treasury), fee_shares);
+}
+
+#[test]
+fn test_set_accrual_fees_rejects_invalid_values() {
+    let setup = setup(0, None);
+    let treasury = Address::generate(&setup.env);
+    let result = setup.vault.try_set_accrual_fees(&501, &0, &treasury);
+    assert_eq!(result, Err(Ok(ContractError::InvalidFee)));
+    let result = setup.vault.try_set_accrual_fees(&0, &5_001, &treasury);
+    assert_eq!(result, Err(Ok(ContractError::InvalidFee)));
+}
+
+#[test]
+fn test_allowlist_mode_restricts_receivers() {
+    let setup = setup(0, None);
+    let approved = funded_user(&setup, 10 * ONE_TOKEN);
+    let other = funded_user(&setup, 10 * ONE_TOKEN);
+    setup.vault.deposit(&(5 * ONE_TOKEN), &other, &other);
+
+    setup.vault.set_allowlist_enabled(&true);
+    assert!(setup.vault.is_allowlist_enabled());
+    let added = setup
+        .vault
+        .add_to_allowlist(&setup.admin, &vec![&setup.env, approved.clone()]);
+    assert_eq!(added, 1);
+    assert!(setup.vault.is_allowlisted(&approved));
+    assert!(!setup.vault.is_allowlisted(&other));
+
+    setup.vault.deposit(&(5 * ONE_TOKEN), &approved, &approved);
+    assert_eq!(setup.vault.max_deposit(&other), 0);
+    assert_eq!(setup.vault.max_mint(&other), 0);
+    assert_eq!(setup.vault.max_deposit(&approved), i128::MAX);
+    let result = setup.vault.try_deposit(&ONE_TOKEN, &approved, &other);
+    assert_eq!(result, Err(Ok(VaultError::NotAllowlisted)));
+    let result = setup.vault.try_mint(&ONE_TOKEN, &other, &other);
+    assert_eq!(result, Err(Ok(VaultError::NotAllowlisted)));
+    let result = setup
+        .vault
+        .try_transfer_shares(&approved, &other, &ONE_TOKEN);
+    assert_eq!(result, Err(Ok(VaultError::NotAllowlisted)));
+    assert!(setup
+        .vault
+        .try_transfer(&approved, &other, &ONE_TOKEN)
+        .is_err());
+
+    // Shares can still move to allowlisted accounts and be redeemed
+    setup.vault.transfer(&other, &approved, &ONE_TOKEN);
+    setup.vault.redeem(&ONE_TOKEN, &other, &other, &other);
+
+    // Disabling the mode lifts the restriction
+    setup.vault.set_allowlist_enabled(&false);
+    assert_eq!(setup.vault.max_deposit(&other), i128::MAX);
+    setup.vault.deposit(&ONE_TOKEN, &other, &other);
+}
+
+#[test]
+fn test_allowlist_managed_in_batches_by_compliance() {
+    let setup = setup(0, None);
+    let compliance = Address::generate(&setup.env);
+    let first = Address::generate(&setup.env);
+    let second = Address::generate(&setup.env);
+    let addresses = vec![&setup.env, first.clone(), second.clone(), first.clone()];
+
+    let result = setup.vault.try_add_to_allowlist(&compliance, &addresses);
+    assert_eq!(result, Err(Ok(VaultError::Unauthorized)));
+
+    setup.vault.grant_role(&Role::Compliance, &compliance);
+    assert_eq!(setup.vault.add_to_allowlist(&compliance, &addresses), 2);
+    assert!(setup.vault.is_allowlisted(&first));
+    assert!(setup.vault.is_allowlisted(&second));
+
+    assert_eq!(
+        setup
+            .vault
+            .remove_from_allowlist(&compliance, &vec![&setup.env, second.clone()]),
+        1
+    );
+    assert!(!setup.vault.is_allowlisted(&second));
+
+    setup.vault.revoke_role(&Role::Compliance, &compliance);
+    let result = setup
+        .vault
+        .try_remove_from_allowlist(&compliance, &addresses);
+    assert_eq!(result, Err(Ok(VaultError::Unauthorized)));
+}
+
+#[test]
+fn test_frozen_account_is_blocked() {
+    let setup = setup(0, None);
+    let frozen = funded_user(&setup, 10 * ONE_TOKEN);
+    let other = funded_user(&setup, 10 * ONE_TOKEN);
+    setup.vault.deposit(&(5 * ONE_TOKEN), &frozen, &frozen);
+    setup.vault.deposit(&(5 * ONE_TOKEN), &other, &other);
+
+    setup.vault.freeze(&frozen);
+    assert!(setup.vault.is_frozen(&frozen));
+    assert_eq!(
+        setup.vault.try_freeze(&frozen),
+        Err(Ok(ContractError::AccountIsAlreadyFrozen))
+    );
+    assert_eq!(setup.vault.max_deposit(&frozen), 0);
+    assert_eq!(setup.vault.max_redeem(&frozen), 0);
+
+    let result = setup.vault.try_deposit(&ONE_TOKEN, &frozen, &frozen);
+    assert_eq!(result, Err(Ok(VaultError::AccountFrozen)));
+    let result = setup.vault.try_deposit(&ONE_TOKEN, &other, &frozen);
+    assert_eq!(result, Err(Ok(VaultError::AccountFrozen)));
+    let result = setup
+        .vault
+        .try_withdraw(&ONE_TOKEN, &frozen, &frozen, &frozen);
+    assert_eq!(result, Err(Ok(VaultError::AccountFrozen)));
+    let result = setup.vault.try_redeem(&ONE_TOKEN, &other, &frozen, &other);
+    assert_eq!(result, Err(Ok(VaultError::AccountFrozen)));
+    let result = setup.vault.try_transfer_shares(&frozen, &other, &ONE_TOKEN);
+    assert_eq!(result, Err(Ok(VaultError::AccountFrozen)));
+    let result = setup.vault.try_transfer_shares(&other, &frozen, &ONE_TOKEN);
+    assert_eq!(result, Err(Ok(VaultError::AccountFrozen)));
+    assert!(setup
+        .vault
+        .try_transfer(&frozen, &other, &ONE_TOKEN)
+        .is_err());
+
+    // The rest of the vault keeps running
+    setup.vault.redeem(&ONE_TOKEN, &other, &other, &other);
+
+    setup.vault.unfreeze(&frozen);
+    assert!(!setup.vault.is_frozen(&frozen));
+    setup.vault.redeem(&ONE_TOKEN, &frozen, &frozen, &frozen);
+}
+
+#[test]
+fn test_guardian_can_pause_but_not_unpause() {
+    let setup = setup(0, None);
+    let guardian = Address::generate(&setup.env);
+    let pauser = Address::generate(&setup.env);
+    setup.vault.grant_role(&Role::Guardian, &guardian);
+    setup.vault.grant_role(&Role::Pauser, &pauser);
+
+    setup.vault.pause_by(&guardian);
+    assert!(setup.vault.is_paused());
+    let result = setup.vault.try_unpause_by(&guardian);
+    assert_eq!(result, Err(Ok(ContractError::Unauthorized)));
+    setup.vault.unpause_by(&pauser);
+    assert!(!setup.vault.is_paused());
+
+    setup.vault.pause_deposit_by(&guardian);
+    let result = setup.vault.try_unpause_by(&guardian);
+    assert_eq!(result, Err(Ok(ContractError::Unauthorized)));
+}
+
+#[test]
+fn test_roles_granted_and_revoked_by_owner() {
+    let setup = setup(0, None);
+    let account = Address::generate(&setup.env);
+    assert!(setup.vault.has_role(&Role::Owner, &setup.admin));
+    assert_eq!(setup.vault.roles_of(&account), vec![&setup.env]);
+
+    setup.vault.grant_role(&Role::FeeManager, &account);
+    setup.vault.grant_role(&Role::Pauser, &account);
+    assert!(setup.vault.has_role(&Role::FeeManager, &account));
+    assert_eq!(
+        setup.vault.roles_of(&account),
+        vec![&setup.env, Role::Pauser, Role::FeeManager]
+    );
+    assert_eq!(
+        setup.vault.try_grant_role(&Role::Pauser, &account),
+        Err(Ok(ContractError::RoleIsAlreadyGranted))
+    );
+    assert_eq!(
+        setup.vault.try_grant_role(&Role::Owner, &account),
+        Err(Ok(ContractError::InvalidRole))
+    );
+
+    // Fee manager configures fees without holding the owner key
+    let recipient = Address::generate(&setup.env);
+    setup.vault.set_fees_by(&account, &10, &10, &recipient);
+
+    setup.vault.revoke_role(&Role::FeeManager, &account);
+    assert!(!setup.vault.has_role(&Role::FeeManager, &account));
+    let result = setup.vault.try_set_fees_by(&account, &20, &20, &recipient);
+    assert_eq!(result, Err(Ok(ContractError::Unauthorized)));
+    assert_eq!(
+        setup.vault.try_revoke_role(&Role::FeeManager, &account),
+        Err(Ok(ContractError::RoleIsAlreadyNotGranted))
+    );
+}
+
+#[test]
+fn test_settlement_requires_market_operator_role() {
+    let setup = setup(0, None);
+    let user = funded_user(&setup, 10 * ONE_TOKEN);
+    setup.vault.deposit(&(10 * ONE_TOKEN), &user, &user);
+    let market = Address::generate(&setup.env);
+
+    let result = setup.vault.try_settle_out_by(&market, &market, &ONE_TOKEN);
+    assert_eq!(result, Err(Ok(VaultError::Unauthorized)));
+    setup.vault.grant_role(&Role::MarketOperator, &market);
+    setup.vault.settle_out_by(&market, &market, &ONE_TOKEN);
+    assert_eq!(setup.asset.balance(&market), ONE_TOKEN);
+    assert_eq!(setup.vault.total_assets(), 9 * ONE_TOKEN);
+}
+
+#[test]
+fn test_set_market_and_compliance_hold_roles() {
+    let setup = setup(0, None);
+    let market = Address::generate(&setup.env);
+    let next_market = Address::generate(&setup.env);
+    let compliance = Address::generate(&setup.env);
+    let account = Address::generate(&setup.env);
+    assert_eq!(
+        setup.vault.try_market_address(),
+        Err(Ok(ContractError::MarketNotSet))
+    );
+    assert_eq!(
+        setup.vault.try_settle_in(&ONE_TOKEN),
+        Err(Ok(VaultError::MarketNotSet))
+    );
+
+    setup.vault.set_market(&market);
+    assert_eq!(setup.vault.market_address(), market);
+    assert!(setup.vault.has_role(&Role::MarketOperator, &market));
+    setup.vault.set_market(&next_market);
+    assert_eq!(setup.vault.market_address(), next_market);
+    assert!(!setup.vault.has_role(&Role::MarketOperator, &market));
+    assert!(setup.vault.has_role(&Role::MarketOperator, &next_market));
+
+    // Revoking the role stops the registered market from settling
+    setup.vault.revoke_role(&Role::MarketOperator, &next_market);
+    assert_eq!(
+        setup.vault.try_settle_out(&account, &ONE_TOKEN),
+        Err(Ok(VaultError::Unauthorized))
+    );
+    assert_eq!(
+        setup.vault.try_settle_in(&ONE_TOKEN),
+        Err(Ok(VaultError::Unauthorized))
+    );
+
+    assert_eq!(setup.vault.compliance_address(), None);
+    setup.vault.set_compliance(&Some(compliance.clone()));
+    assert_eq!(setup.vault.compliance_address(), Some(compliance.clone()));
+    assert!(setup.vault.has_role(&Role::Compliance, &compliance));
+    setup.vault.freeze_by(&compliance, &account);
+    assert!(setup.vault.is_frozen(&account));
+    // The owner keeps freezing rights next to the compliance account
+    setup.vault.unfreeze_by(&setup.admin, &account);
+    setup.vault.freeze(&account);
+    assert!(setup.vault.is_frozen(&account));
+
+    setup.vault.set_compliance(&None);
+    assert_eq!(setup.vault.compliance_address(), None);
+    assert!(!setup.vault.has_role(&Role::Compliance, &compliance));
+    assert_eq!(
+        setup.vault.try_unfreeze_by(&compliance, &account),
+        Err(Ok(ContractError::Unauthorized))
+    );
+}
+
+#[test]
+fn test_two_step_administrator_transfer() {
+    let setup = setup(0, None);
+    let new_admin = Address::generate(&setup.env);
+    assert_eq!(
+        setup.vault.try_accept_administrator(),
+        Err(Ok(ContractError::NoPendingAdmin))
+    );
+
+    setup.vault.propose_administrator(&new_admin);
+    assert_eq!(setup.vault.pending_administrator(), Some(new_admin.clone()));
+    // Nothing changes until the proposed address accepts
+    assert_eq!(setup.vault.administrator_address(), setup.admin);
+
+    setup.vault.cancel_administrator_transfer();
+    assert_eq!(setup.vault.pending_administrator(), None);
+    assert_eq!(
+        setup.vault.try_cancel_administrator_transfer(),
+        Err(Ok(ContractError::NoPendingAdmin))
+    );
+
+    setup.vault.propose_administrator(&new_admin);
+    setup.vault.accept_administrator();
+    assert_eq!(setup.vault.administrator_address(), new_admin);
+    assert_eq!(setup.vault.pending_administrator(), None);
+    assert!(setup.vault.has_role(&Role::Owner, &new_admin));
+    assert!(!setup.vault.has_role(&Role::Owner, &setup.admin));
+    let result = setup.vault.try_pause_by(&setup.admin);
+    assert_eq!(result, Err(Ok(ContractError::Unauthorized)));
+    setup.vault.pause();
+}
+
+#[test]
+fn test_migrate_from_legacy_layout() {
+    let setup = setup(0, None);
+    assert_eq!(setup.vault.schema_version(), 2);
+    assert_eq!(
+        setup.vault.try_migrate(),
+        Err(Ok(ContractError::AlreadyMigrated))
+    );
+
+    // Vaults deployed before versioning read the token balance and store no schema version
+    let user = funded_user(&setup, 10 * ONE_TOKEN);
+    setup
+        .asset
+        .transfer(&user, &setup.vault.contract_address(), &(10 * ONE_TOKEN));
+    setup.env.as_contract(&setup.vault.address, || {
+        let storage = setup.env.storage().instance();
+        storage.remove(&DataKey::SchemaVersion);
+        storage.remove(&DataKey::TotalManagedAssets);
+        storage.remove(&DataKey::LastFeeAccrual);
+    });
+    assert_eq!(setup.vault.schema_version(), 0);
+
+    assert_eq!(setup.vault.migrate(), 2);
+    assert_eq!(setup.vault.schema_version(), 2);
+    assert_eq!(setup.vault.total_assets(), 10 * ONE_TOKEN);
+    assert_eq!(
+        setup.vault.try_migrate(),
+        Err(Ok(ContractError::AlreadyMigrated))
+    );
+}
+
+#[test]
+fn test_upgrade_then_migrate() {
+    let setup = setup(0, None);
+    // Running the uploaded code in the VM costs more than the default test budget
+    setup.env.budget().reset_unlimited();
+    let user = funded_user(&setup, 10 * ONE_TOKEN);
+    setup.vault.deposit(&(10 * ONE_TOKEN), &user, &user);
+    // Release build of this contract, regenerated with `make testdata`
+    let wasm_hash = setup
+        .env
+        .deployer()
+        .upload_contract_wasm(include_bytes!("../testdata/vault.wasm").as_slice());
+
+    // Only the administrator can replace the code
+    let stranger = Address::generate(&setup.env);
+    setup.env.mock_auths(&[MockAuth {
+        address: &stranger,
+        invoke: &MockAuthInvoke {
+            contract: &setup.vault.address,
+            fn_name: "upgrade",
+            args: (wasm_hash.clone(),).into_val(&setup.env),
+            sub_invokes: &[],
+        },
+    }]);
+    assert_eq!(
+        setup.vault.try_upgrade(&wasm_hash),
+        Err(Err(InvokeError::Abort))
+    );
+
+    setup.env.mock_all_auths();
+    assert!(setup.vault.upgrade(&wasm_hash));
+    assert_eq!(
+        setup.env.auths(),
+        std::vec![(
+            setup.admin.clone(),
+            AuthorizedInvocation {
+                function: AuthorizedFunction::Contract((
+                    setup.vault.address.clone(),
+                    Symbol::new(&setup.env, "upgrade"),
+                    (wasm_hash.clone(),).into_val(&setup.env),
+                )),
+                sub_invocations: std::vec![],
+            }
+        )]
+    );
+
+    // The new code keeps the storage, a layout written before versioning is converted by migrate
+    setup.env.as_contract(&setup.vault.address, || {
+        let storage = setup.env.storage().instance();
+        storage.remove(&DataKey::SchemaVersion);
+    });
+    assert_eq!(setup.vault.schema_version(), 0);
+    assert_eq!(setup.vault.migrate(), 2);
+    assert_eq!(setup.vault.schema_version(), 2);
+    assert_eq!(setup.vault.total_assets(), 10 * ONE_TOKEN);
+    assert_eq!(setup.vault.balance(&user), 10 * ONE_TOKEN);
+}
+
+#[test]
+fn test_redeem_request_claimable_after_unlock() {
+    let setup = setup(0, None);
+    let user = funded_user(&setup, 10 * ONE_TOKEN);
+    let shares = setup.vault.deposit(&(10 * ONE_TOKEN), &user, &user);
+
+    setup.env.ledger().set_timestamp(LOCK_TIMESTAMP);
+    assert_eq!(
+        setup.vault.try_redeem(&shares, &user, &user, &user),
+        Err(Ok(VaultError::Locked))
+    );
+    assert_eq!(
+        setup
+            .vault
+            .try_request_redeem(&(shares + 1), &user, &user, &user),
+        Err(Ok(VaultError::InsufficientShareBalance))
+    );
+    assert_eq!(
+        setup.vault.request_redeem(&shares, &user, &user, &user),
+        shares
+    );
+    assert_eq!(setup.vault.balance(&user), 0);
+    assert_eq!(setup.vault.pending_redeem_request(&user), shares);
+    assert_eq!(setup.vault.claimable_redeem_request(&user), 0);
+    assert_eq!(
+        setup.vault.try_claim(&user, &user),
+        Err(Ok(VaultError::NothingToClaim))
+    );
+
+    setup.env.ledger().set_timestamp(UNLOCK_TIMESTAMP + 1);
+    assert_eq!(setup.vault.pending_redeem_request(&user), 0);
+    assert_eq!(setup.vault.claimable_redeem_request(&user), shares);
+    assert_eq!(setup.vault.claim(&user, &user), 10 * ONE_TOKEN);
+    assert_eq!(setup.asset.balance(&user), 10 * ONE_TOKEN);
+    assert_eq!(setup.vault.claimable_redeem_request(&user), 0);
+    assert_eq!(setup.vault.total_shares(), 0);
+}
+
+#[test]
+fn test_redeem_request_claimable_after_its_own_window() {
+    let setup = setup(0, None);
+    setup
+        .vault
+        .add_lock_window(&setup.admin, &3_000_000, &4_000_000);
+    let user = funded_user(&setup, 10 * ONE_TOKEN);
+    let other = funded_user(&setup, 10 * ONE_TOKEN);
+    let shares = setup.vault.deposit(&(10 * ONE_TOKEN), &user, &user);
+    let other_shares = setup.vault.deposit(&(10 * ONE_TOKEN), &other, &other);
+
+    setup.env.ledger().set_timestamp(LOCK_TIMESTAMP);
+    setup.vault.request_redeem(&shares, &user, &user, &user);
+
+    // Nothing reads the request until the next window is locked
+    setup.env.ledger().set_timestamp(3_000_000);
+    assert_eq!(setup.vault.pending_redeem_request(&user), 0);
+    assert_eq!(setup.vault.claimable_redeem_request(&user), shares);
+
+    // Requests made in this window wait for its end
+    setup
+        .vault
+        .request_redeem(&other_shares, &other, &other, &other);
+    assert_eq!(setup.vault.pending_redeem_request(&other), other_shares);
+    setup.env.ledger().set_timestamp(4_000_001);
+    assert_eq!(setup.vault.claimable_redeem_request(&other), other_shares);
+    assert_eq!(setup.vault.claim(&user, &user), 10 * ONE_TOKEN);
+    assert_eq!(setup.vault.claim(&other, &other), 10 * ONE_TOKEN);
+}
+
+#[test]
+fn test_claim_rejected_inside_lock_window() {
+    let setup = setup(0, None);
+    let market = Address::generate(&setup.env);
+    setup.vault.grant_role(&Role::MarketOperator, &market);
+    let user = funded_user(&setup, 10 * ONE_TOKEN);
+    let shares = setup.vault.deposit(&(10 * ONE_TOKEN), &user, &user);
+
+    // Requested just before the lock, the shares are claimable right away
+    setup.env.ledger().set_timestamp(LOCK_TIMESTAMP - 1);
+    setup.vault.request_redeem(&shares, &user, &user, &user);
+    assert_eq!(setup.vault.claimable_redeem_request(&user), shares);
+
+    // but not while the outcome of the event is still open
+    setup.env.ledger().set_timestamp(LOCK_TIMESTAMP);
+    assert_eq!(
+        setup.vault.try_claim(&user, &user),
+        Err(Ok(VaultError::Locked))
+    );
+    assert_eq!(setup.vault.claimable_redeem_request(&user), shares);
+
+    // Once the market has settled the claim pays out at the settled share price
+    setup
+        .vault
+        .settle_out_by(&market, &market, &(4 * ONE_TOKEN));
+    assert_eq!(setup.vault.claim(&user, &user), 6 * ONE_TOKEN);
+}
+
+#[test]
+fn test_escrowed_shares_kept_apart_from_dead_shares() {
+    let setup = setup(0, Some(ONE_TOKEN));
+    let vault_address = setup.vault.contract_address();
+    let dead_shares = setup.vault.balance(&vault_address);
+    assert!(dead_shares > 0);
+    let user = funded_user(&setup, 10 * ONE_TOKEN);
+    let shares = setup.vault.deposit(&(10 * ONE_TOKEN), &user, &user);
+
+    setup.env.ledger().set_timestamp(LOCK_TIMESTAMP);
+    setup.vault.request_redeem(&shares, &user, &user, &user);
+    assert_eq!(setup.vault.escrowed_shares(), shares);
+    assert_eq!(setup.vault.balance(&vault_address), dead_shares);
+    assert_eq!(setup.vault.total_shares(), dead_shares + shares);
+
+    setup.env.ledger().set_timestamp(UNLOCK_TIMESTAMP + 1);
+    assert_eq!(setup.vault.claim(&user, &user), 10 * ONE_TOKEN);
+    assert_eq!(setup.vault.escrowed_shares(), 0);
+    assert_eq!(setup.vault.balance(&vault_address), dead_shares);
+    assert_eq!(setup.vault.total_shares(), dead_shares);
+    assert_eq!(setup.vault.total_assets(), ONE_TOKEN);
+}
+
+#[test]
+fn test_redeem_request_claimable_after_settlement() {
+    let setup = setup(0, None);
+    let market = Address::generate(&setup.env);
+    setup.vault.grant_role(&Role::MarketOperator, &market);
+    let user = funded_user(&setup, 10 * ONE_TOKEN);
+    let late_user = funded_user(&setup, 10 * ONE_TOKEN);
+    let shares = setup.vault.deposit(&(10 * ONE_TOKEN), &user, &user);
+    let late_shares = setup
+        .vault
+        .deposit(&(10 * ONE_TOKEN), &late_user, &late_user);
+
+    setup.env.ledger().set_timestamp(LOCK_TIMESTAMP);
+    setup.vault.request_redeem(&shares, &user, &user, &user);
+    // The market pays out half of the collateral, requesting holders share the loss
+    setup
+        .vault
+        .settle_out_by(&market, &market, &(10 * ONE_TOKEN));
+    assert_eq!(setup.vault.claimable_redeem_request(&user), shares);
+
+    // Requests made after the settlement wait for the next one
+    setup
+        .vault
+        .request_redeem(&late_shares, &late_user, &late_user, &late_user);
+    assert_eq!(setup.vault.pending_redeem_request(&late_user), late_shares);
+    assert_eq!(
+        setup.vault.try_claim(&late_user, &late_user),
+        Err(Ok(VaultError::NothingToClaim))
+    );
+
+    assert_eq!(setup.vault.claim(&user, &user), 5 * ONE_TOKEN);
+    assert_eq!(setup.asset.balance(&user), 5 * ONE_TOKEN);
+    assert_eq!(setup.vault.claimable_redeem_request(&user), 0);
+}
+
+#[test]
+fn test_lock_window_schedule() {
+    let setup = setup(0, None);
+    let first = LockWindow {
+        lock_timestamp: LOCK_TIMESTAMP,
+        unlock_timestamp: UNLOCK_TIMESTAMP,
+    };
+    let second = LockWindow {
+        lock_timestamp: 3_000_000,
+        unlock_timestamp: 4_000_000,
+    };
+    assert_eq!(
+        setup
+            .vault
+            .try_add_lock_window(&setup.admin, &2_500_000, &2_400_000),
+        Err(Ok(ContractError::InvalidLockTimes))
+    );
+    assert_eq!(
+        setup
+            .vault
+            .try_add_lock_window(&setup.admin, &UNLOCK_TIMESTAMP, &3_000_000),
+        Err(Ok(ContractError::LockWindowOverlap))
+    );
+    let market = Address::generate(&setup.env);
+    assert_eq!(
+        setup
+            .vault
+            .try_add_lock_window(&market, &3_000_000, &4_000_000),
+        Err(Ok(ContractError::Unauthorized))
+    );
+    setup.vault.grant_role(&Role::MarketOperator, &market);
+    assert_eq!(
+        setup.vault.add_lock_window(&market, &3_000_000, &4_000_000),
+        2
+    );
+    assert_eq!(
+        setup.vault.lock_windows(),
+        vec![&setup.env, first.clone(), second.clone()]
+    );
+
+    assert_eq!(setup.vault.current_lock_window(), None);
+    assert_eq!(setup.vault.next_lock_window(), Some(first.clone()));
+    let user = funded_user(&setup, 10 * ONE_TOKEN);
+    setup.vault.deposit(&ONE_TOKEN, &user, &user);
+
+    setup.env.ledger().set_timestamp(LOCK_TIMESTAMP);
+    assert_eq!(setup.vault.current_lock_window(), Some(first));
+    assert_eq!(setup.vault.next_lock_window(), Some(second.clone()));
+    assert_eq!(
+        setup.vault.try_deposit(&ONE_TOKEN, &user, &user),
+        Err(Ok(VaultError::Locked))
+    );
+
+    // Open between the two windows, the lock views move on to the next window
+    setup.env.ledger().set_timestamp(UNLOCK_TIMESTAMP + 1);
+    assert_eq!(setup.vault.current_lock_window(), None);
+    assert_eq!(setup.vault.lock_timestamp(), second.lock_timestamp);
+    assert_eq!(setup.vault.unlock_timestamp(), second.unlock_timestamp);
+    setup.vault.deposit(&ONE_TOKEN, &user, &user);
+
+    setup.env.ledger().set_timestamp(3_500_000);
+    assert_eq!(setup.vault.current_lock_window(), Some(second));
+    assert_eq!(setup.vault.next_lock_window(), None);
+    assert_eq!(
+        setup.vault.try_deposit(&ONE_TOKEN, &user, &user),
+        Err(Ok(VaultError::Locked))
+    );
+
+    // Windows that have passed are dropped when the next one is appended
+    setup.env.ledger().set_timestamp(5_000_000);
+    assert_eq!(
+        setup
+            .vault
+            .add_lock_window(&setup.admin, &6_000_000, &7_000_000),
+        1
+    );
+}
+
+#[test]
+fn test_migrate_single_lock_window() {
+    let setup = setup(0, None);
+    let window = LockWindow {
+        lock_timestamp: LOCK_TIMESTAMP,
+        unlock_timestamp: UNLOCK_TIMESTAMP,
+    };
+    // Vaults of schema version 1 store the lock window as two timestamps
+    setup.env.as_contract(&setup.vault.address, || {
+        let storage = setup.env.storage().instance();
+        storage.remove(&DataKey::LockWindows);
+        storage.set(&DataKey::LockTimestamp, &LOCK_TIMESTAMP);
+        storage.set(&DataKey::UnlockTimestamp, &UNLOCK_TIMESTAMP);
+        storage.set(&DataKey::SchemaVersion, &1u32);
+    });
+    assert_eq!(setup.vault.lock_windows(), vec![&setup.env, window.clone()]);
+    setup.env.ledger().set_timestamp(LOCK_TIMESTAMP);
+    assert_eq!(setup.vault.current_lock_window(), Some(window.clone()));
+
+    assert_eq!(setup.vault.migrate(), 2);
+    assert_eq!(setup.vault.lock_windows(), vec![&setup.env, window]);
+    setup.env.as_contract(&setup.vault.address, || {
+        let storage = setup.env.storage().instance();
+        assert!(!storage.has(&DataKey::LockTimestamp));
+        assert!(!storage.has(&DataKey::UnlockTimestamp));
+    });
+}
+
+#[test]
+fn test_reschedule_lock_window_before_it_starts() {
+    let setup = setup(0, None);
+    setup
+        .vault
+        .add_lock_window(&setup.admin, &3_000_000, &4_000_000);
+    assert_eq!(
+        setup.vault.try_reschedule_lock_window(
+            &setup.admin,
+            &LOCK_TIMESTAMP,
+            &1_500_000,
+            &1_400_000
+        ),
+        Err(Ok(ContractError::InvalidLockTimes))
+    );
+    assert_eq!(
+        setup
+            .vault
+            .try_reschedule_lock_window(&setup.admin, &1, &1_500_000, &1_600_000),
+        Err(Ok(ContractError::LockWindowNotFound))
+    );
+    assert_eq!(
+        setup.vault.try_reschedule_lock_window(
+            &setup.admin,
+            &LOCK_TIMESTAMP,
+            &1_500_000,
+            &3_000_000
+        ),
+        Err(Ok(ContractError::LockWindowOverlap))
+    );
+
+    setup
+        .vault
+        .reschedule_lock_window(&setup.admin, &LOCK_TIMESTAMP, &1_500_000, &2_500_000);
+    assert_eq!(setup.vault.lock_timestamp(), 1_500_000);
+    assert_eq!(setup.vault.unlock_timestamp(), 2_500_000);
+    setup.env.ledger().set_timestamp(LOCK_TIMESTAMP);
+    assert_eq!(setup.vault.current_lock_window(), None);
+
+    setup.env.ledger().set_timestamp(1_500_000);
+    assert_eq!(
+        setup
+            .vault
+            .try_reschedule_lock_window(&setup.admin, &1_500_000, &2_000_000, &2_500_000),
+        Err(Ok(ContractError::LockWindowStarted))
+    );
+}
+
+#[test]
+fn test_slippage_protected_variants() {
+    let setup = setup(0, None);
+    let user = funded_user(&setup, 10 * ONE_TOKEN);
+    let deadline: u64 = 100;
+    let shares = setup.vault.preview_deposit(&(5 * ONE_TOKEN));
+
+    // A failed check reverts the deposit itself
+    assert_eq!(
+        setup.vault.try_deposit_min_shares(
+            &(5 * ONE_TOKEN),
+            &user,
+            &user,
+            &(shares + 1),
+            &deadline
+        ),
+        Err(Ok(VaultError::InsufficientSharesOut))
+    );
+    assert_eq!(setup.asset.balance(&user), 10 * ONE_TOKEN);
+    assert_eq!(setup.vault.balance(&user), 0);
+    assert_eq!(
+        setup
+            .vault
+            .deposit_min_shares(&(5 * ONE_TOKEN), &user, &user, &shares, &deadline),
+        shares
+    );
+
+    let assets = setup.vault.preview_mint(&shares);
+    assert_eq!(
+        setup
+            .vault
+            .try_mint_max_assets(&shares, &user, &user, &(assets - 1), &deadline),
+        Err(Ok(VaultError::ExcessiveAssetsIn))
+    );
+    assert_eq!(
+        setup
+            .vault
+            .mint_max_assets(&shares, &user, &user, &assets, &deadline),
+        assets
+    );
+
+    let burned = setup.vault.preview_withdraw(&ONE_TOKEN);
+    assert_eq!(
+        setup.vault.try_withdraw_max_shares(
+            &ONE_TOKEN,
+            &user,
+            &user,
+            &user,
+            &(burned - 1),
+            &deadline
+        ),
+        Err(Ok(VaultError::ExcessiveSharesBurned))
+    );
+    setup
+        .vault
+        .withdraw_max_shares(&ONE_TOKEN, &user, &user, &user, &burned, &deadline);
+
+    let paid = setup.vault.preview_redeem(&shares);
+    assert_eq!(
+        setup
+            .vault
+            .try_redeem_min_assets(&shares, &user, &user, &user, &(paid + 1), &deadline),
+        Err(Ok(VaultError::InsufficientAssetsOut))
+    );
+    setup.env.ledger().set_timestamp(deadline + 1);
+    assert_eq!(
+        setup
+            .vault
+            .try_redeem_min_assets(&shares, &user, &user, &user, &paid, &deadline),
+        Err(Ok(VaultError::DeadlineExpired))
+    );
+    assert_eq!(
+        setup
+            .vault
+            .redeem_min_assets(&shares, &user, &user, &user, &paid, &(deadline + 1)),
+        paid
+    );
+}
+
+fn sign_permit(
+    setup: &Setup,
+    signing_key: &SigningKey,
+    owner: &Address,
+    spender: &Address,
+    amount: i128,
+    deadline: u64,
+) -> BytesN<64> {
+    let message = PermitMessage {
+        vault: setup.vault.address.clone(),
+        owner: owner.clone(),
+        spender: spender.clone(),
+        amount,
+        expiration_ledger: setup.env.ledger().sequence() + 100,
+        nonce: setup.vault.nonces(owner),
+        deadline,
+    };
+    let signature = Sign::sign(signing_key, message).unwrap();
+    BytesN::from_array(&setup.env, &signature)
+}
+
+#[test]
+fn test_permit_lets_relayer_withdraw() {
+    let setup = setup(0, None);
+    let user = funded_user(&setup, 10 * ONE_TOKEN);
+    let relayer = Address::generate(&setup.env);
+    setup.vault.deposit(&(10 * ONE_TOKEN), &user, &user);
+    let signing_key = SigningKey::from_bytes(&[7; 32]);
+    let public_key = BytesN::from_array(&setup.env, &signing_key.verifying_key().to_bytes());
+    let expiration_ledger = setup.env.ledger().sequence() + 100;
+    let deadline: u64 = 100;
+
+    let signature = sign_permit(&setup, &signing_key, &user, &relayer, ONE_TOKEN, deadline);
+    assert_eq!(
+        setup.vault.try_permit(
+            &user,
+            &relayer,
+            &ONE_TOKEN,
+            &expiration_ledger,
+            &deadline,
+            &signature
+        ),
+        Err(Ok(VaultError::PermitSignerNotSet))
+    );
+    setup
+        .vault
+        .set_permit_signer(&user, &Some(public_key.clone()));
+    assert_eq!(setup.vault.permit_signer(&user), Some(public_key));
+
+    setup.vault.permit(
+        &user,
+        &relayer,
+        &ONE_TOKEN,
+        &expiration_ledger,
+        &deadline,
+        &signature,
+    );
+    assert_eq!(setup.vault.nonces(&user), 1);
+    assert_eq!(setup.vault.allowance(&user, &relayer), ONE_TOKEN);
+    setup.vault.redeem(&ONE_TOKEN, &relayer, &relayer, &user);
+    assert_eq!(setup.asset.balance(&relayer), ONE_TOKEN);
+
+    // The same signature can not be used again once the nonce moved on
+    assert!(setup
+        .vault
+        .try_permit(
+            &user,
+            &relayer,
+            &ONE_TOKEN,
+            &expiration_ledger,
+            &deadline,
+            &signature
+        )
+        .is_err());
+    // Nor can the signed amount be changed
+    let signature = sign_permit(&setup, &signing_key, &user, &relayer, ONE_TOKEN, deadline);
+    assert!(setup
+        .vault
+        .try_permit(
+            &user,
+            &relayer,
+            &(2 * ONE_TOKEN),
+            &expiration_ledger,
+            &deadline,
+            &signature
+        )
+        .is_err());
+    assert_eq!(setup.vault.nonces(&user), 1);
+
+    // Reading the nonce keeps it alive, like the signer
+    setup
+        .env
+        .ledger()
+        .with_mut(|ledger| ledger.sequence_number += 2 * 17_280);
+    assert_eq!(setup.vault.nonces(&user), 1);
+    setup.env.as_contract(&setup.vault.address, || {
+        let ttl = setup
+            .env
+            .storage()
+            .persistent()
+            .get_ttl(&DataKey::PermitNonce(user.clone()));
+        assert_eq!(ttl, 30 * 17_280);
+    });
+
+    setup.env.ledger().set_timestamp(deadline + 1);
+    assert_eq!(
+        setup.vault.try_permit(
+            &user,
+            &relayer,
+            &ONE_TOKEN,
+            &expiration_ledger,
+            &deadline,
+            &signature
+        ),
+        Err(Ok(VaultError::DeadlineExpired))
+    );
+}
+
+#[test]
+fn test_allowance_api() {
+    let setup = setup(0, None);
+    let owner = Address::generate(&setup.env);
+    let spender = Address::generate(&setup.env);
+    let expiry_ledger = setup.env.ledger().sequence() + 1_000;
+    assert_eq!(setup.vault.allowance_details(&owner, &spender), None);
+    assert_eq!(
+        setup
+            .vault
+            .try_increase_allowance(&owner, &spender, &ONE_TOKEN),
+        Err(Ok(VaultError::NoAllowance))
+    );
+
+    setup
+        .vault
+        .approve(&owner, &spender, &ONE_TOKEN, &expiry_ledger);
+    assert_eq!(
+        setup.vault.allowance_details(&owner, &spender),
+        Some(AllowanceData {
+            amount: ONE_TOKEN,
+            expiry_ledger
+        })
+    );
+    // Allowances are kept in temporary storage only
+    setup.env.as_contract(&setup.vault.address, || {
+        let key = DataKey::Allowance(owner.clone(), spender.clone());
+        assert!(setup.env.storage().temporary().has(&key));
+        assert!(!setup.env.storage().persistent().has(&key));
+    });
+
+    assert_eq!(
+        setup
+            .vault
+            .increase_allowance(&owner, &spender, &(2 * ONE_TOKEN)),
+        3 * ONE_TOKEN
+    );
+    assert_eq!(
+        setup
+            .vault
+            .try_decrease_allowance(&owner, &spender, &(4 * ONE_TOKEN)),
+        Err(Ok(VaultError::InsufficientAllowance))
+    );
+    assert_eq!(
+        setup.vault.decrease_allowance(&owner, &spender, &ONE_TOKEN),
+        2 * ONE_TOKEN
+    );
+    assert_eq!(
+        setup.vault.allowance_details(&owner, &spender),
+        Some(AllowanceData {
+            amount: 2 * ONE_TOKEN,
+            expiry_ledger
+        })
+    );
+
+    setup.vault.revoke_allowance(&owner, &spender);
+    assert_eq!(setup.vault.allowance_details(&owner, &spender), None);
+    assert_eq!(
+        setup.vault.try_revoke_allowance(&owner, &spender),
+        Err(Ok(VaultError::NoAllowance))
+    );
+
+    setup
+        .vault
+        .approve(&owner, &spender, &ONE_TOKEN, &expiry_ledger);
+    setup.env.ledger().set_sequence_number(expiry_ledger + 1);
+    assert_eq!(setup.vault.allowance_details(&owner, &spender), None);
+    assert_eq!(setup.vault.allowance(&owner, &spender), 0);
+}
+
+#[test]
+fn test_persistent_allowance_moves_to_temporary_storage() {
+    let setup = setup(0, None);
+    let owner = Address::generate(&setup.env);
+    let spender = Address::generate(&setup.env);
+    let allowance = AllowanceData {
+        amount: 2 * ONE_TOKEN,
+        expiry_ledger: setup.env.ledger().sequence() + 1_000,
+    };
+    let key = DataKey::Allowance(owner.clone(), spender.clone());
+    // Allowances written by earlier versions are in persistent storage
+    setup.env.as_contract(&setup.vault.address, || {
+        setup.env.storage().persistent().set(&key, &allowance);
+    });
+    assert_eq!(
+        setup.vault.allowance_details(&owner, &spender),
+        Some(allowance)
+    );
+
+    setup.vault.decrease_allowance(&owner, &spender, &ONE_TOKEN);
+    setup.env.as_contract(&setup.vault.address, || {
+        assert!(setup.env.storage().temporary().has(&key));
+        assert!(!setup.env.storage().persistent().has(&key));
+    });
+    assert_eq!(setup.vault.allowance(&owner, &spender), ONE_TOKEN);
+}
+
+#[test]
+fn test_operator_acts_without_allowance() {
+    let setup = setup(0, None);
+    let user = funded_user(&setup, 10 * ONE_TOKEN);
+    let operator = Address::generate(&setup.env);
+    let shares = setup.vault.deposit(&(10 * ONE_TOKEN), &user, &user);
+    assert_eq!(
+        setup.vault.try_set_operator(&user, &user, &true),
+        Err(Ok(VaultError::CannotApproveOrTransferToSelf))
+    );
+    assert_eq!(
+        setup
+            .vault
+            .try_redeem(&ONE_TOKEN, &operator, &operator, &user),
+        Err(Ok(VaultError::NoAllowance))
+    );
+
+    setup.vault.set_operator(&user, &operator, &true);
+    assert!(setup.vault.is_operator(&user, &operator));
+    setup.vault.redeem(&ONE_TOKEN, &operator, &operator, &user);
+    setup.vault.withdraw(&ONE_TOKEN, &operator, &user, &user);
+    assert_eq!(setup.asset.balance(&operator), ONE_TOKEN);
+    assert_eq!(setup.asset.balance(&user), ONE_TOKEN);
+
+    // Operators can also request and claim redemptions on behalf of the controller
+    setup.env.ledger().set_timestamp(LOCK_TIMESTAMP);
+    let remaining = shares - 2 * ONE_TOKEN;
+    setup
+        .vault
+        .request_redeem(&remaining, &operator, &user, &user);
+    setup.env.ledger().set_timestamp(UNLOCK_TIMESTAMP + 1);
+    let stranger = Address::generate(&setup.env);
+    assert_eq!(
+        setup.vault.try_claim_by(&stranger, &user, &stranger),
+        Err(Ok(VaultError::Unauthorized))
+    );
+    setup.vault.claim_by(&operator, &user, &user);
+    assert_eq!(setup.asset.balance(&user), 9 * ONE_TOKEN);
+
+    setup.vault.set_operator(&user, &operator, &false);
+    assert!(!setup.vault.is_operator(&user, &operator));
+}
+
+#[test]
+fn test_holder_registry_follows_balances() {
+    let setup = setup(0, None);
+    let alice = funded_user(&setup, 10 * ONE_TOKEN);
+    let bob = funded_user(&setup, 10 * ONE_TOKEN);
+    let carol = funded_user(&setup, 10 * ONE_TOKEN);
+    assert_eq!(setup.vault.holder_count(), 0);
+    let alice_shares = setup.vault.deposit(&(10 * ONE_TOKEN), &alice, &alice);
+    let bob_shares = setup.vault.deposit(&(10 * ONE_TOKEN), &bob, &bob);
+    let carol_shares = setup.vault.deposit(&(10 * ONE_TOKEN), &carol, &carol);
+    assert_eq!(setup.vault.holder_count(), 3);
+    assert_eq!(
+        setup.vault.holders(&0, &10),
+        vec![
+            &setup.env,
+            (alice.clone(), alice_shares),
+            (bob.clone(), bob_shares),
+            (carol.clone(), carol_shares)
+        ]
+    );
+
+    // The last holder moves into the position of a holder that exits
+    setup.vault.transfer(&alice, &bob, &alice_shares);
+    assert_eq!(setup.vault.holder_count(), 2);
+    assert_eq!(
+        setup.vault.holders(&0, &1),
+        vec![&setup.env, (carol.clone(), carol_shares)]
+    );
+    assert_eq!(
+        setup.vault.holders(&1, &10),
+        vec![&setup.env, (bob.clone(), alice_shares + bob_shares)]
+    );
+    assert_eq!(setup.vault.holders(&2, &10), vec![&setup.env]);
+
+    setup.vault.redeem(&carol_shares, &carol, &carol, &carol);
+    assert_eq!(
+        setup.vault.holders(&0, &10),
+        vec![&setup.env, (bob, alice_shares + bob_shares)]
+    );
+}
+
+#[test]
+fn test_holder_registry_leaves_out_vault_and_stays_alive() {
+    let setup = setup(0, Some(ONE_TOKEN));
+    let vault_address = setup.vault.contract_address();
+    assert!(setup.vault.balance(&vault_address) > 0);
+    assert_eq!(setup.vault.holder_count(), 0);
+    setup
+        .vault
+        .register_holders(&vec![&setup.env, vault_address.clone()]);
+    assert_eq!(setup.vault.holder_count(), 0);
+
+    let user = funded_user(&setup, 11 * ONE_TOKEN);
+    let shares = setup.vault.deposit(&(10 * ONE_TOKEN), &user, &user);
+    assert_eq!(
+        setup.vault.holders(&0, &10),
+        vec![&setup.env, (user.clone(), shares)]
+    );
+
+    // Extending a balance extends the registry entries of the holder too
+    setup.vault.extend_vault_ttl();
+    setup
+        .env
+        .ledger()
+        .with_mut(|ledger| ledger.sequence_number += 2 * 17_280);
+    assert!(setup.vault.extend_shares_ttl(&user));
+    setup.env.as_contract(&setup.vault.address, || {
+        let persistent = setup.env.storage().persistent();
+        let month_in_ledgers: u32 = 30 * 17_280;
+        assert_eq!(
+            persistent.get_ttl(&DataKey::HolderIndex(user.clone())),
+            month_in_ledgers
+        );
+        assert_eq!(persistent.get_ttl(&DataKey::HolderAt(0)), month_in_ledgers);
+    });
+
+    // and so does any balance change
+    setup
+        .env
+        .ledger()
+        .with_mut(|ledger| ledger.sequence_number += 2 * 17_280);
+    setup.vault.deposit(&ONE_TOKEN, &user, &user);
+    setup.env.as_contract(&setup.vault.address, || {
+        let persistent = setup.env.storage().persistent();
+        let month_in_ledgers: u32 = 30 * 17_280;
+        assert_eq!(
+            persistent.get_ttl(&DataKey::TotalSharesOf(user.clone())),
+            month_in_ledgers
+        );
+        assert_eq!(persistent.get_ttl(&DataKey::HolderAt(0)), month_in_ledgers);
+    });
+}
+
+#[test]
+fn test_register_holders_of_existing_vault() {
+    let setup = setup(0, None);
+    let user = funded_user(&setup, 10 * ONE_TOKEN);
+    let shares = setup.vault.deposit(&(10 * ONE_TOKEN), &user, &user);
+    // Vaults deployed before the registry have balances but no registered holders
+    setup.env.as_contract(&setup.vault.address, || {
+        setup.env.storage().instance().remove(&DataKey::HolderCount);
+        let storage = setup.env.storage().persistent();
+        storage.remove(&DataKey::HolderAt(0));
+        storage.remove(&DataKey::HolderIndex(user.clone()));
+    });
+    assert_eq!(setup.vault.holder_count(), 0);
+
+    let stranger = Address::generate(&setup.env);
+    assert_eq!(
+        setup
+            .vault
+            .register_holders(&vec![&setup.env, user.clone(), stranger, user.clone()]),
+        1
+    );
+    assert_eq!(
+        setup.vault.holders(&0, &10),
+        vec![&setup.env, (user, shares)]
+    );
+}
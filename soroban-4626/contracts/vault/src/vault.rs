@@ -21,25 +21,54 @@
  */
 // https://github.com/OpenZeppelin/openzeppelin-contracts/blob/master/contracts/token/ERC20/extensions/ERC4626.sol
 use soroban_sdk::{
-    contract, contractclient, contractimpl, contractmeta, symbol_short, token, Address, Env, String,
+    contract, contractclient, contractmeta, panic_with_error, symbol_short, token,
+    token::TokenInterface, vec, xdr::ToXdr, Address, BytesN, Env, EnvBase, Map, String, Val, Vec,
 };
 
 use crate::{
-    allowance::{_approve_allowance, _calculate_expiry_ledger, _spend_allowance},
+    allowance::{
+        _approve_allowance, _calculate_expiry_ledger, _decrease_allowance, _increase_allowance,
+        _read_allowance_amount, _read_live_allowance, _spend_allowance, AllowanceData,
+    },
     errors::{ContractError, VaultError},
-    ivault::IPublicVault,
-    math::{
-        mul_div, safe_add_i128, safe_add_u32, safe_div, safe_mul, safe_pow, safe_sub_i128, Rounding,
+    fees::{
+        _fee_on_raw, _fee_on_total, _fee_shares, _management_fee, _performance_fee, _share_price,
+        MAX_FEE_BASIS_POINTS, MAX_MANAGEMENT_FEE_BASIS_POINTS, MAX_PERFORMANCE_FEE_BASIS_POINTS,
     },
+    ivault::IPublicVault,
+    keys::{DepositLimits, FeeConfig, LockWindow, PermitMessage, RedeemRequest},
+    math::{mul_div, safe_add_i128, safe_add_u32, safe_mul, safe_pow, safe_sub_i128, Rounding},
+    roles::{Role, ALL_ROLES},
     storage::{
-        deposit_paused, extend_contract_ttl, extend_persistence_all_ttl, has_administrator,
-        is_paused, read_administrator, read_asset_address, read_asset_decimals, read_asset_name,
-        read_asset_symbol, read_lock_timestamp, read_total_shares, read_total_shares_of,
-        read_unlock_timestamp, remove_deposit_paused, remove_paused, remove_withdraw_paused,
-        withdraw_paused, write_administrator, write_asset_address, write_asset_decimals,
-        write_asset_name, write_asset_symbol, write_deposit_paused, write_lock_timestamp,
-        write_paused, write_total_shares, write_total_shares_of, write_unlock_timestamp,
-        write_withdraw_paused, BUMP_THRESHOLD, EXTEND_TO_DAYS,
+        allowlist_enabled, deposit_paused, extend_contract_ttl, extend_persistence_all_ttl,
+        extend_shares_of_ttl, has_administrator, has_base_unit_accounting, has_lock_windows,
+        has_role, has_total_managed_assets, is_allowlisted, is_frozen, is_operator, is_paused,
+        read_account_deposit_cap, read_administrator, read_asset_address, read_asset_decimals,
+        read_asset_name, read_asset_symbol, read_compliance_address, read_decimals_offset,
+        read_entry_fee_basis_points, read_escrowed_shares, read_exit_fee_basis_points,
+        read_fee_recipient, read_high_water_mark, read_holder_at, read_holder_count,
+        read_holder_index, read_last_fee_accrual, read_last_settlement, read_legacy_lock_window,
+        read_lock_windows, read_management_fee_basis_points, read_market_address, read_min_deposit,
+        read_pending_administrator, read_performance_fee_basis_points, read_permit_nonce,
+        read_permit_signer, read_redeem_request, read_schema_version, read_settlement_epoch,
+        read_total_assets_cap, read_total_managed_assets, read_total_shares, read_total_shares_of,
+        read_treasury, remove_allowlist_enabled, remove_allowlisted, remove_deposit_paused,
+        remove_frozen, remove_holder_at, remove_holder_index, remove_legacy_lock_window,
+        remove_operator, remove_paused, remove_pending_administrator, remove_permit_signer,
+        remove_redeem_request, remove_role, remove_withdraw_paused, withdraw_paused,
+        write_account_deposit_cap, write_administrator, write_allowlist_enabled, write_allowlisted,
+        write_asset_address, write_asset_decimals, write_asset_name, write_asset_symbol,
+        write_base_unit_accounting, write_compliance_address, write_decimals_offset,
+        write_deposit_paused, write_entry_fee_basis_points, write_escrowed_shares,
+        write_exit_fee_basis_points, write_fee_recipient, write_frozen, write_high_water_mark,
+        write_holder_at, write_holder_count, write_holder_index, write_last_fee_accrual,
+        write_last_settlement, write_lock_windows, write_management_fee_basis_points,
+        write_market_address, write_min_deposit, write_operator, write_paused,
+        write_pending_administrator, write_performance_fee_basis_points, write_permit_nonce,
+        write_permit_signer, write_redeem_request, write_role, write_schema_version,
+        write_settlement_epoch, write_total_assets_cap, write_total_managed_assets,
+        write_total_shares, write_total_shares_of, write_treasury, write_withdraw_paused,
+        BUMP_THRESHOLD, EXTEND_TO_DAYS,
     },
 };
 
@@ -51,9 +80,20 @@ contractmeta!(
 #[contract]
 pub struct Vault;
 
+const SHARE_NAME_PREFIX: &str = "Vault ";
+const SHARE_SYMBOL_PREFIX: &str = "v";
+const MAX_DECIMALS_OFFSET: u32 = 18;
+const MAX_LOCK_WINDOWS: u32 = 52; // One year of weekly windows
+const MAX_HOLDERS_PAGE: u32 = 100;
+// Storage layout version, bump together with a new step in _migrate_from
+const SCHEMA_VERSION: u32 = 2;
+
 // Public functions
 #[contractclient(name = "VaultContractClient")]
-#[contractimpl]
+#[cfg_attr(
+    not(all(feature = "library", target_family = "wasm")),
+    soroban_sdk::contractimpl
+)]
 impl IPublicVault for Vault {
     fn initialize(
         env: Env,
@@ -61,6 +101,8 @@ impl IPublicVault for Vault {
         asset_address: Address,
         lock_timestamp: u64,
         unlock_timestamp: u64,
+        decimals_offset: u32,
+        seed_assets: Option<i128>,
     ) -> Result<(String, String, u32), ContractError> {
         admin.require_auth();
 
@@ -70,6 +112,12 @@ impl IPublicVault for Vault {
             if lock_timestamp > unlock_timestamp {
                 return Err(ContractError::InvalidLockTimes);
             }
+            if decimals_offset > MAX_DECIMALS_OFFSET {
+                return Err(ContractError::InvalidDecimalsOffset);
+            }
+            if seed_assets.is_some_and(|assets| assets <= 0) {
+                return Err(ContractError::InvalidSeedAmount);
+            }
             // Before passing asset_address, verify the underlying asset contract exists and implements the token trait, otherwise initialization will fail
             let token_client = token::Client::new(&env, &asset_address);
             let name: String = token_client.name();
@@ -84,8 +132,24 @@ impl IPublicVault for Vault {
 
             write_total_shares(&env, &0i128);
             write_administrator(&env, &admin);
-            write_lock_timestamp(&env, &lock_timestamp);
-            write_unlock_timestamp(&env, &unlock_timestamp);
+            write_lock_windows(
+                &env,
+                &vec![
+                    &env,
+                    LockWindow {
+                        lock_timestamp,
+                        unlock_timestamp,
+                    },
+                ],
+            );
+            write_base_unit_accounting(&env);
+            write_decimals_offset(&env, &decimals_offset);
+            write_total_managed_assets(&env, &0i128);
+            write_schema_version(&env, &SCHEMA_VERSION);
+
+            if let Some(assets) = seed_assets {
+                Self::_seed_dead_shares(&env, &admin, assets)?;
+            }
 
             extend_contract_ttl(&env, BUMP_THRESHOLD, EXTEND_TO_DAYS);
             extend_persistence_all_ttl(&env, BUMP_THRESHOLD, EXTEND_TO_DAYS);
@@ -111,10 +175,60 @@ impl IPublicVault for Vault {
         }
     }
 
+    fn pending_administrator(env: &Env) -> Result<Option<Address>, ContractError> {
+        if has_administrator(env) {
+            Ok(read_pending_administrator(env))
+        } else {
+            Err(ContractError::NotInitialized)
+        }
+    }
+
+    fn propose_administrator(env: Env, new_admin: Address) -> Result<bool, ContractError> {
+        // Administrator changes only once the new address accepts, so a mistyped address can not strand the vault
+        if has_administrator(&env) {
+            let admin: Address = read_administrator(&env);
+            admin.require_auth();
+            write_pending_administrator(&env, &new_admin);
+            Self::_emit_admin_proposed_event(&env, &admin, &new_admin);
+            Ok(true)
+        } else {
+            Err(ContractError::NotInitialized)
+        }
+    }
+
+    fn accept_administrator(env: Env) -> Result<bool, ContractError> {
+        if has_administrator(&env) {
+            let new_admin: Address =
+                read_pending_administrator(&env).ok_or(ContractError::NoPendingAdmin)?;
+            new_admin.require_auth();
+            let previous_admin: Address = read_administrator(&env);
+            write_administrator(&env, &new_admin);
+            remove_pending_administrator(&env);
+            Self::_emit_admin_accepted_event(&env, &previous_admin, &new_admin);
+            Ok(true)
+        } else {
+            Err(ContractError::NotInitialized)
+        }
+    }
+
+    fn cancel_administrator_transfer(env: Env) -> Result<bool, ContractError> {
+        if has_administrator(&env) {
+            let admin: Address = read_administrator(&env);
+            admin.require_auth();
+            let pending: Address =
+                read_pending_administrator(&env).ok_or(ContractError::NoPendingAdmin)?;
+            remove_pending_administrator(&env);
+            Self::_emit_admin_cancelled_event(&env, &admin, &pending);
+            Ok(true)
+        } else {
+            Err(ContractError::NotInitialized)
+        }
+    }
+
     fn asset_decimals(env: &Env) -> Result<u32, ContractError> {
         if has_administrator(&env) {
             let decimals: u32 = read_asset_decimals(&env);
-            let result: u32 = safe_add_u32(decimals, Self::_decimals_offset());
+            let result: u32 = safe_add_u32(decimals, Self::_decimals_offset(env));
             Ok(result)
         } else {
             Err(ContractError::NotInitialized)
@@ -153,13 +267,9 @@ impl IPublicVault for Vault {
     }
 
     fn total_assets(env: &Env) -> Result<i128, ContractError> {
+        // Assets tracked by the vault itself, tokens sent directly to the vault are not included
         if has_administrator(&env) {
-            let asset_address: Address = read_asset_address(&env);
-            let token_client = token::Client::new(&env, &asset_address);
-            let this_address: Address = Self::contract_address(&env);
-            let balance: i128 = token_client.balance(&this_address);
-            let return_balance: i128 = Self::_divide_by_decimals(env, balance);
-            Ok(return_balance)
+            Ok(Self::_managed_assets(env))
         } else {
             Err(ContractError::NotInitialized)
         }
@@ -183,9 +293,54 @@ impl IPublicVault for Vault {
         }
     }
 
+    fn holder_count(env: &Env) -> Result<u32, ContractError> {
+        if has_administrator(env) {
+            Ok(read_holder_count(env))
+        } else {
+            Err(ContractError::NotInitialized)
+        }
+    }
+
+    fn holders(env: &Env, start: u32, limit: u32) -> Result<Vec<(Address, i128)>, ContractError> {
+        // Positions change when a holder exits, so a snapshot should be read within one ledger.
+        // The dead shares in the vault's own balance and escrowed shares are not listed.
+        if has_administrator(env) {
+            let end: u32 = start
+                .saturating_add(limit.min(MAX_HOLDERS_PAGE))
+                .min(read_holder_count(env));
+            let mut holders: Vec<(Address, i128)> = Vec::new(env);
+            for position in start..end {
+                let holder: Address = read_holder_at(env, position);
+                let shares: i128 = read_total_shares_of(env, holder.clone());
+                holders.push_back((holder, shares));
+            }
+            Ok(holders)
+        } else {
+            Err(ContractError::NotInitialized)
+        }
+    }
+
+    fn register_holders(env: Env, holders: Vec<Address>) -> Result<u32, ContractError> {
+        // Holders of vaults deployed before the registry are added on their next balance change,
+        // or by the admin listing them here. Addresses without shares are skipped.
+        if has_administrator(&env) {
+            let admin: Address = read_administrator(&env);
+            admin.require_auth();
+            for holder in holders.iter() {
+                if read_total_shares_of(&env, holder.clone()) != 0 {
+                    Self::_add_holder(&env, &holder);
+                }
+            }
+            Ok(read_holder_count(&env))
+        } else {
+            Err(ContractError::NotInitialized)
+        }
+    }
+
     fn lock_timestamp(env: Env) -> Result<u64, ContractError> {
+        // Lock of the current window, or of the next one when the vault is not locked
         if has_administrator(&env) {
-            let lock: u64 = read_lock_timestamp(&env);
+            let lock: u64 = Self::_scheduled_lock_window(&env).lock_timestamp;
             Ok(lock)
         } else {
             Err(ContractError::NotInitialized)
@@ -194,13 +349,129 @@ impl IPublicVault for Vault {
 
     fn unlock_timestamp(env: Env) -> Result<u64, ContractError> {
         if has_administrator(&env) {
-            let unlock: u64 = read_unlock_timestamp(&env);
+            let unlock: u64 = Self::_scheduled_lock_window(&env).unlock_timestamp;
             Ok(unlock)
         } else {
             Err(ContractError::NotInitialized)
         }
     }
 
+    fn lock_windows(env: &Env) -> Result<Vec<LockWindow>, ContractError> {
+        if has_administrator(env) {
+            Ok(Self::_lock_windows(env))
+        } else {
+            Err(ContractError::NotInitialized)
+        }
+    }
+
+    fn current_lock_window(env: &Env) -> Result<Option<LockWindow>, ContractError> {
+        if has_administrator(env) {
+            Ok(Self::_current_lock_window(env))
+        } else {
+            Err(ContractError::NotInitialized)
+        }
+    }
+
+    fn next_lock_window(env: &Env) -> Result<Option<LockWindow>, ContractError> {
+        if has_administrator(env) {
+            let now: u64 = env.ledger().timestamp();
+            Ok(Self::_lock_windows(env)
+                .iter()
+                .find(|window| now < window.lock_timestamp))
+        } else {
+            Err(ContractError::NotInitialized)
+        }
+    }
+
+    fn add_lock_window(
+        env: Env,
+        caller: Address,
+        lock_timestamp: u64,
+        unlock_timestamp: u64,
+    ) -> Result<u32, ContractError> {
+        // Windows are kept in order and must not overlap, past windows are dropped on append
+        if has_administrator(&env) {
+            if !Self::_authorize(&env, &caller, &[Role::Owner, Role::MarketOperator]) {
+                return Err(ContractError::Unauthorized);
+            }
+            let now: u64 = env.ledger().timestamp();
+            if lock_timestamp > unlock_timestamp || unlock_timestamp < now {
+                return Err(ContractError::InvalidLockTimes);
+            }
+            let mut windows: Vec<LockWindow> = Vec::new(&env);
+            for window in Self::_lock_windows(&env).iter() {
+                if window.unlock_timestamp >= now {
+                    windows.push_back(window);
+                }
+            }
+            if windows
+                .last()
+                .is_some_and(|last| lock_timestamp <= last.unlock_timestamp)
+            {
+                return Err(ContractError::LockWindowOverlap);
+            }
+            if windows.len() >= MAX_LOCK_WINDOWS {
+                return Err(ContractError::TooManyLockWindows);
+            }
+            let window = LockWindow {
+                lock_timestamp,
+                unlock_timestamp,
+            };
+            windows.push_back(window.clone());
+            write_lock_windows(&env, &windows);
+            Self::_emit_lock_window_event(&env, &caller, window);
+            Ok(windows.len())
+        } else {
+            Err(ContractError::NotInitialized)
+        }
+    }
+
+    fn reschedule_lock_window(
+        env: Env,
+        caller: Address,
+        current_lock_timestamp: u64,
+        lock_timestamp: u64,
+        unlock_timestamp: u64,
+    ) -> Result<bool, ContractError> {
+        // Moves a window that has not started yet, e.g. when the underlying event is rescheduled
+        if has_administrator(&env) {
+            if !Self::_authorize(&env, &caller, &[Role::Owner, Role::MarketOperator]) {
+                return Err(ContractError::Unauthorized);
+            }
+            let now: u64 = env.ledger().timestamp();
+            if lock_timestamp > unlock_timestamp || lock_timestamp < now {
+                return Err(ContractError::InvalidLockTimes);
+            }
+            let mut windows: Vec<LockWindow> = Self::_lock_windows(&env);
+            let index: u32 = windows
+                .iter()
+                .position(|window| window.lock_timestamp == current_lock_timestamp)
+                .ok_or(ContractError::LockWindowNotFound)? as u32;
+            let previous: LockWindow = windows.get_unchecked(index);
+            if now >= previous.lock_timestamp {
+                return Err(ContractError::LockWindowStarted);
+            }
+            let after_previous: bool =
+                index == 0 || windows.get_unchecked(index - 1).unlock_timestamp < lock_timestamp;
+            let before_next: bool = windows
+                .get(index + 1)
+                .is_none_or(|next| unlock_timestamp < next.lock_timestamp);
+            if !after_previous || !before_next {
+                return Err(ContractError::LockWindowOverlap);
+            }
+            let window = LockWindow {
+                lock_timestamp,
+                unlock_timestamp,
+            };
+            windows.set(index, window.clone());
+            write_lock_windows(&env, &windows);
+            Self::_emit_lock_window_rescheduled_event(&env, &caller, previous, window);
+            Ok(true)
+        } else {
+            Err(ContractError::NotInitialized)
+        }
+    }
+
     fn convert_to_shares(env: &Env, assets: i128) -> Result<i128, ContractError> {
         Self::_convert_to_shares(env, assets, Rounding::Floor)
     }
@@ -210,51 +481,81 @@ impl IPublicVault for Vault {
     }
 
     fn convert_to_shares_simulate(
-        _env: &Env,
+        env: &Env,
         assets: i128,
         total_assets: i128,
         total_shares: i128,
     ) -> Result<i128, ContractError> {
-        Self::_convert_to_shares_simulate(assets, total_assets, total_shares, Rounding::Floor)
+        Self::_convert_to_shares_simulate(env, assets, total_assets, total_shares, Rounding::Floor)
     }
 
     fn convert_to_assets_simulate(
-        _env: &Env,
+        env: &Env,
         shares: i128,
         total_shares: i128,
         total_assets: i128,
     ) -> Result<i128, ContractError> {
-        Self::_convert_to_assets_simulate(shares, total_shares, total_assets, Rounding::Floor)
+        Self::_convert_to_assets_simulate(env, shares, total_shares, total_assets, Rounding::Floor)
     }
 
-    fn max_deposit(_: &Env, _address: Address) -> i128 {
-        i128::MAX
+    fn max_deposit(env: &Env, receiver: Address) -> i128 {
+        // Report 0 whenever a deposit would be rejected, as required by ERC-4626
+        if !Self::_deposits_open(env)
+            || is_frozen(env, receiver.clone())
+            || Self::_ensure_allowlisted(env, &receiver).is_err()
+        {
+            return 0;
+        }
+        let mut max_assets: i128 = i128::MAX;
+        if let Some(cap) = read_total_assets_cap(env) {
+            max_assets = max_assets.min(cap - Self::_managed_assets(env)).max(0);
+        }
+        if let Some(cap) = read_account_deposit_cap(env) {
+            let held: i128 = Self::_assets_of(env, &receiver);
+            max_assets = max_assets.min(cap - held).max(0);
+        }
+        match read_min_deposit(env) {
+            // Remaining room below the minimum deposit size cannot be used
+            Some(min) if max_assets < min => 0,
+            _ => max_assets,
+        }
     }
 
-    fn max_mint(_: &Env, _address: Address) -> i128 {
-        i128::MAX
+    fn max_mint(env: &Env, receiver: Address) -> i128 {
+        let max_assets: i128 = Self::max_deposit(env, receiver);
+        if max_assets == i128::MAX {
+            i128::MAX
+        } else {
+            Self::_unwrap_or_panic(
+                env,
+                Self::_convert_to_shares(env, max_assets, Rounding::Floor),
+            )
+        }
     }
 
     fn max_withdraw(env: &Env, owner: Address) -> i128 {
-        match Self::balance_of_shares(&env, owner) {
-            Ok(value) => match Self::_convert_to_assets(&env, value, Rounding::Floor) {
-                Ok(val) => val,
-                Err(er) => panic!("Call failed with error: {:?}", er),
-            },
-            Err(e) => panic!("Call failed with error: {:?}", e),
+        if !Self::_withdrawals_open(env) || is_frozen(env, owner.clone()) {
+            return 0;
         }
+        // Exit fee is paid on top of the withdrawn assets
+        let assets: i128 = Self::_assets_of(env, &owner);
+        safe_sub_i128(
+            assets,
+            _fee_on_total(assets, read_exit_fee_basis_points(env)),
+        )
     }
 
     fn max_redeem(env: &Env, owner: Address) -> i128 {
-        match Self::balance_of_shares(&env, owner) {
-            Ok(value) => value,
-            Err(e) => panic!("Call failed with error: {:?}", e),
+        if !Self::_withdrawals_open(env) || is_frozen(env, owner.clone()) {
+            return 0;
         }
+        read_total_shares_of(env, owner)
     }
 
     fn preview_deposit(env: &Env, assets: i128) -> Result<i128, ContractError> {
         if has_administrator(&env) {
-            Self::_convert_to_shares(&env, assets, Rounding::Floor)
+            let fee: i128 = _fee_on_total(assets, read_entry_fee_basis_points(env));
+            Self::_convert_to_shares(env, safe_sub_i128(assets, fee), Rounding::Floor)
         } else {
             Err(ContractError::NotInitialized)
         }
@@ -262,7 +563,9 @@ impl IPublicVault for Vault {
 
     fn preview_mint(env: &Env, shares: i128) -> Result<i128, ContractError> {
         if has_administrator(&env) {
-            Self::_convert_to_assets(&env, shares, Rounding::Ceil)
+            let assets: i128 = Self::_convert_to_assets(env, shares, Rounding::Ceil)?;
+            let fee: i128 = _fee_on_raw(assets, read_entry_fee_basis_points(env));
+            Ok(safe_add_i128(assets, fee))
         } else {
             Err(ContractError::NotInitialized)
         }
@@ -270,7 +573,8 @@ impl IPublicVault for Vault {
 
     fn preview_withdraw(env: &Env, assets: i128) -> Result<i128, ContractError> {
         if has_administrator(&env) {
-            Self::_convert_to_shares(&env, assets, Rounding::Ceil)
+            let fee: i128 = _fee_on_raw(assets, read_exit_fee_basis_points(env));
+            Self::_convert_to_shares(env, safe_add_i128(assets, fee), Rounding::Ceil)
         } else {
             Err(ContractError::NotInitialized)
         }
@@ -278,7 +582,9 @@ impl IPublicVault for Vault {
 
     fn preview_redeem(env: &Env, shares: i128) -> Result<i128, ContractError> {
         if has_administrator(&env) {
-            Self::_convert_to_assets(&env, shares, Rounding::Floor)
+            let assets: i128 = Self::_convert_to_assets(env, shares, Rounding::Floor)?;
+            let fee: i128 = _fee_on_total(assets, read_exit_fee_basis_points(env));
+            Ok(safe_sub_i128(assets, fee))
         } else {
             Err(ContractError::NotInitialized)
         }
@@ -292,15 +598,28 @@ impl IPublicVault for Vault {
     ) -> Result<i128, VaultError> {
         if has_administrator(&env) {
             caller.require_auth();
-            if assets <= 0 {
+            Self::_accrue_fees(&env);
+            if !has_base_unit_accounting(&env) {
+                Err(VaultError::BaseUnitMigrationRequired)
+            } else if assets <= 0 {
                 Err(VaultError::ZeroAssets)
             } else {
+                Self::_ensure_deposit_allowed(&env)?;
+                Self::_ensure_not_frozen(&env, &caller)?;
+                Self::_ensure_not_frozen(&env, &receiver)?;
+                Self::_ensure_allowlisted(&env, &receiver)?;
+                Self::_check_deposit_limits(&env, &receiver, assets)?;
                 let max_assets: i128 = Self::max_deposit(&env, receiver.clone());
                 if assets > max_assets {
                     Err(VaultError::ERC4626ExceededMaxDeposit)
                 } else {
-                    let shares: i128 = Self::preview_deposit(&env, assets).unwrap();
-                    Self::_deposit(&env, &caller, &receiver, assets, shares);
+                    let shares: i128 = Self::preview_deposit(&env, assets)
+                        .map_err(|_| VaultError::ArithmeticError)?;
+                    if shares <= 0 {
+                        // Depositing would only donate the assets to existing holders
+                        return Err(VaultError::ZeroShares);
+                    }
+                    Self::_deposit(&env, &caller, &receiver, assets, shares)?;
                     Ok(shares)
                 }
             }
@@ -317,15 +636,24 @@ impl IPublicVault for Vault {
     ) -> Result<i128, VaultError> {
         if has_administrator(&env) {
             caller.require_auth();
-            if shares <= 0 {
+            Self::_accrue_fees(&env);
+            if !has_base_unit_accounting(&env) {
+                Err(VaultError::BaseUnitMigrationRequired)
+            } else if shares <= 0 {
                 Err(VaultError::ZeroShares)
             } else {
+                Self::_ensure_deposit_allowed(&env)?;
+                Self::_ensure_not_frozen(&env, &caller)?;
+                Self::_ensure_not_frozen(&env, &receiver)?;
+                Self::_ensure_allowlisted(&env, &receiver)?;
+                let assets: i128 =
+                    Self::preview_mint(&env, shares).map_err(|_| VaultError::ArithmeticError)?;
+                Self::_check_deposit_limits(&env, &receiver, assets)?;
                 let max_shares: i128 = Self::max_mint(&env, receiver.clone());
                 if shares > max_shares {
                     Err(VaultError::ERC4626ExceededMaxMint)
                 } else {
-                    let assets: i128 = Self::preview_mint(&env, shares).unwrap();
-                    Self::_deposit(&env, &caller, &receiver, assets, shares);
+                    Self::_deposit(&env, &caller, &receiver, assets, shares)?;
                     Ok(assets)
                 }
             }
@@ -343,15 +671,23 @@ impl IPublicVault for Vault {
     ) -> Result<i128, VaultError> {
         if has_administrator(&env) {
             caller.require_auth();
-            if assets <= 0 {
+            Self::_accrue_fees(&env);
+            if !has_base_unit_accounting(&env) {
+                Err(VaultError::BaseUnitMigrationRequired)
+            } else if assets <= 0 {
                 Err(VaultError::ZeroAssets)
             } else {
+                Self::_ensure_withdraw_allowed(&env)?;
+                Self::_ensure_not_frozen(&env, &caller)?;
+                Self::_ensure_not_frozen(&env, &owner)?;
+                Self::_ensure_not_frozen(&env, &receiver)?;
                 let max_assets: i128 = Self::max_withdraw(&env, owner.clone());
                 if assets > max_assets {
                     Err(VaultError::ERC4626ExceededMaxWithdraw)
                 } else {
-                    let shares: i128 = Self::preview_withdraw(&env, assets).unwrap();
-                    Self::_withdraw(&env, &caller, &receiver, &owner, assets, shares);
+                    let shares: i128 = Self::preview_withdraw(&env, assets)
+                        .map_err(|_| VaultError::ArithmeticError)?;
+                    Self::_withdraw(&env, &caller, &receiver, &owner, assets, shares)?;
                     Ok(shares)
                 }
             }
@@ -369,15 +705,27 @@ impl IPublicVault for Vault {
     ) -> Result<i128, VaultError> {
         if has_administrator(&env) {
             caller.require_auth();
-            if shares <= 0 {
+            Self::_accrue_fees(&env);
+            if !has_base_unit_accounting(&env) {
+                Err(VaultError::BaseUnitMigrationRequired)
+            } else if shares <= 0 {
                 Err(VaultError::ZeroShares)
             } else {
+                Self::_ensure_withdraw_allowed(&env)?;
+                Self::_ensure_not_frozen(&env, &caller)?;
+                Self::_ensure_not_frozen(&env, &owner)?;
+                Self::_ensure_not_frozen(&env, &receiver)?;
                 let max_shares: i128 = Self::max_redeem(&env, owner.clone());
                 if shares > max_shares {
                     Err(VaultError::ERC4626ExceededMaxRedeem)
                 } else {
-                    let assets: i128 = Self::preview_redeem(&env, shares).unwrap();
-                    Self::_withdraw(&env, &caller, &receiver, &owner, assets, shares);
+                    let assets: i128 = Self::preview_redeem(&env, shares)
+                        .map_err(|_| VaultError::ArithmeticError)?;
+                    if assets <= 0 {
+                        // Redeeming would burn the shares without paying anything out
+                        return Err(VaultError::ZeroAssets);
+                    }
+                    Self::_withdraw(&env, &caller, &receiver, &owner, assets, shares)?;
                     Ok(assets)
                 }
             }
@@ -386,6 +734,236 @@ impl IPublicVault for Vault {
         }
     }
 
+    // Slippage protected variants, a failed check reverts the whole operation
+
+    fn deposit_min_shares(
+        env: Env,
+        assets: i128,
+        caller: Address,
+        receiver: Address,
+        min_shares: i128,
+        deadline: u64,
+    ) -> Result<i128, VaultError> {
+        Self::_ensure_before_deadline(&env, deadline)?;
+        let shares: i128 = Self::deposit(env, assets, caller, receiver)?;
+        if shares < min_shares {
+            Err(VaultError::InsufficientSharesOut)
+        } else {
+            Ok(shares)
+        }
+    }
+
+    fn mint_max_assets(
+        env: Env,
+        shares: i128,
+        caller: Address,
+        receiver: Address,
+        max_assets: i128,
+        deadline: u64,
+    ) -> Result<i128, VaultError> {
+        Self::_ensure_before_deadline(&env, deadline)?;
+        let assets: i128 = Self::mint(env, shares, caller, receiver)?;
+        if assets > max_assets {
+            Err(VaultError::ExcessiveAssetsIn)
+        } else {
+            Ok(assets)
+        }
+    }
+
+    fn withdraw_max_shares(
+        env: Env,
+        assets: i128,
+        caller: Address,
+        receiver: Address,
+        owner: Address,
+        max_shares: i128,
+        deadline: u64,
+    ) -> Result<i128, VaultError> {
+        Self::_ensure_before_deadline(&env, deadline)?;
+        let shares: i128 = Self::withdraw(env, assets, caller, receiver, owner)?;
+        if shares > max_shares {
+            Err(VaultError::ExcessiveSharesBurned)
+        } else {
+            Ok(shares)
+        }
+    }
+
+    fn redeem_min_assets(
+        env: Env,
+        shares: i128,
+        caller: Address,
+        receiver: Address,
+        owner: Address,
+        min_assets: i128,
+        deadline: u64,
+    ) -> Result<i128, VaultError> {
+        Self::_ensure_before_deadline(&env, deadline)?;
+        let assets: i128 = Self::redeem(env, shares, caller, receiver, owner)?;
+        if assets < min_assets {
+            Err(VaultError::InsufficientAssetsOut)
+        } else {
+            Ok(assets)
+        }
+    }
+
+    fn pending_redeem_request(env: &Env, controller: Address) -> i128 {
+        if has_administrator(env) {
+            Self::_redeem_request(env, &controller).pending_shares
+        } else {
+            0
+        }
+    }
+
+    fn claimable_redeem_request(env: &Env, controller: Address) -> i128 {
+        if has_administrator(env) {
+            Self::_redeem_request(env, &controller).claimable_shares
+        } else {
+            0
+        }
+    }
+
+    fn escrowed_shares(env: &Env) -> i128 {
+        // Shares of all open redeem requests, still part of the total supply until claimed
+        if has_administrator(env) {
+            read_escrowed_shares(env)
+        } else {
+            0
+        }
+    }
+
+    fn request_redeem(
+        env: Env,
+        shares: i128,
+        caller: Address,
+        controller: Address,
+        owner: Address,
+    ) -> Result<i128, VaultError> {
+        // Shares are escrowed by the vault until the request becomes claimable,
+        // which is after the lock window or after the next settlement by the market
+        if has_administrator(&env) {
+            caller.require_auth();
+            Self::_accrue_fees(&env);
+            if !has_base_unit_accounting(&env) {
+                Err(VaultError::BaseUnitMigrationRequired)
+            } else if shares <= 0 {
+                Err(VaultError::ZeroShares)
+            } else {
+                Self::_ensure_contract_not_paused(&env)?;
+                Self::_ensure_withdraw_not_paused(&env)?;
+                Self::_ensure_not_frozen(&env, &caller)?;
+                Self::_ensure_not_frozen(&env, &owner)?;
+                Self::_ensure_not_frozen(&env, &controller)?;
+                let owner_shares: i128 = read_total_shares_of(&env, owner.clone());
+                if shares > owner_shares {
+                    return Err(VaultError::InsufficientShareBalance);
+                }
+                Self::_spend_allowance_unless_operator(&env, &owner, &caller, shares)?;
+                let vault_address: Address = Self::contract_address(&env);
+                Self::_write_shares_of(&env, &owner, safe_sub_i128(owner_shares, shares));
+                write_escrowed_shares(&env, &safe_add_i128(read_escrowed_shares(&env), shares));
+                Self::_emit_transfer_event(&env, &owner, &vault_address, shares);
+                let mut request: RedeemRequest = Self::_redeem_request(&env, &controller);
+                match Self::_current_lock_window(&env) {
+                    Some(window) => {
+                        request.pending_shares = safe_add_i128(request.pending_shares, shares);
+                        request.epoch = read_settlement_epoch(&env);
+                        request.unlock_timestamp = window.unlock_timestamp;
+                    }
+                    // Outside of a lock window nothing holds the shares back
+                    None => {
+                        request.claimable_shares = safe_add_i128(request.claimable_shares, shares);
+                    }
+                }
+                write_redeem_request(&env, controller.clone(), &request);
+                Self::_emit_redeem_request_event(&env, &caller, &controller, &owner, shares);
+                Ok(request.pending_shares)
+            }
+        } else {
+            Err(VaultError::AdministratorError)
+        }
+    }
+
+    fn claim(env: Env, controller: Address, receiver: Address) -> Result<i128, VaultError> {
+        // Claimed shares are redeemed at the share price of the claim, exit fee included
+        Self::claim_by(env, controller.clone(), controller, receiver)
+    }
+
+    fn claim_by(
+        env: Env,
+        caller: Address,
+        controller: Address,
+        receiver: Address,
+    ) -> Result<i128, VaultError> {
+        // Operators of the controller claim on its behalf
+        if has_administrator(&env) {
+            caller.require_auth();
+            if caller != controller && !is_operator(&env, controller.clone(), caller.clone()) {
+                return Err(VaultError::Unauthorized);
+            }
+            Self::_accrue_fees(&env);
+            Self::_ensure_contract_not_paused(&env)?;
+            Self::_ensure_withdraw_not_paused(&env)?;
+            Self::_ensure_not_frozen(&env, &controller)?;
+            Self::_ensure_not_frozen(&env, &receiver)?;
+            let mut request: RedeemRequest = Self::_redeem_request(&env, &controller);
+            let shares: i128 = request.claimable_shares;
+            if shares <= 0 {
+                return Err(VaultError::NothingToClaim);
+            }
+            Self::_ensure_claim_allowed(&env)?;
+            let assets: i128 =
+                Self::preview_redeem(&env, shares).map_err(|_| VaultError::ArithmeticError)?;
+            if assets <= 0 {
+                return Err(VaultError::ZeroAssets);
+            }
+            // Escrowed shares are not in the vault's balance, only the total supply shrinks
+            write_escrowed_shares(&env, &safe_sub_i128(read_escrowed_shares(&env), shares));
+            write_total_shares(&env, &safe_sub_i128(read_total_shares(&env), shares));
+            Self::_emit_burn_event(&env, &Self::contract_address(&env), shares);
+            Self::_pay_out(&env, &controller, &receiver, assets)?;
+            request.claimable_shares = 0;
+            if request.pending_shares > 0 {
+                write_redeem_request(&env, controller.clone(), &request);
+            } else {
+                remove_redeem_request(&env, controller.clone());
+            }
+            Self::_emit_withdraw_event(&env, &caller, &receiver, &controller, assets, shares);
+            Ok(assets)
+        } else {
+            Err(VaultError::AdministratorError)
+        }
+    }
+
+    fn is_operator(env: Env, owner: Address, operator: Address) -> bool {
+        is_operator(&env, owner, operator)
+    }
+
+    fn set_operator(
+        env: Env,
+        owner: Address,
+        operator: Address,
+        approved: bool,
+    ) -> Result<bool, VaultError> {
+        // Operators act for the owner without an allowance, until the owner revokes them.
+        // They withdraw, redeem, request redemptions and claim; deposits and mints are not delegated.
+        if has_administrator(&env) {
+            owner.require_auth();
+            if owner == operator {
+                Err(VaultError::CannotApproveOrTransferToSelf)
+            } else {
+                if approved {
+                    write_operator(&env, owner.clone(), operator.clone());
+                } else {
+                    remove_operator(&env, owner.clone(), operator.clone());
+                }
+                Self::_emit_operator_event(&env, &owner, &operator, approved);
+                Ok(true)
+            }
+        } else {
+            Err(VaultError::AdministratorError)
+        }
+    }
+
     fn approve_shares(
         env: Env,
         owner: Address,
@@ -393,6 +971,7 @@ impl IPublicVault for Vault {
         approve_amount: i128,
         expire_in_days: u32,
     ) -> Result<bool, VaultError> {
+        // For an explicit expiration ledger use approve from the token interface
         if has_administrator(&env) {
             owner.require_auth();
             if approve_amount <= 0 {
@@ -411,54 +990,186 @@ impl IPublicVault for Vault {
         }
     }
 
-    fn transfer_shares(
+    fn allowance_details(env: Env, owner: Address, spender: Address) -> Option<AllowanceData> {
+        // None when there is no allowance or it has expired
+        _read_live_allowance(&env, &owner, &spender)
+    }
+
+    fn increase_allowance(
         env: Env,
         owner: Address,
-        receiver: Address,
-        shares_amount: i128,
-    ) -> Result<bool, VaultError> {
+        spender: Address,
+        amount: i128,
+    ) -> Result<i128, VaultError> {
         if has_administrator(&env) {
             owner.require_auth();
-            if shares_amount <= 0 {
+            if amount <= 0 {
                 Err(VaultError::InvalidAmount)
             } else {
-                let owner_shares: i128 = read_total_shares_of(&env, owner.clone());
-                if owner_shares < shares_amount {
-                    Err(VaultError::InvalidAmount)
-                } else {
-                    if owner == receiver {
-                        Err(VaultError::CannotApproveOrTransferToSelf)
-                    } else {
-                        // Change owner's and receiver's token balances
-                        // Total shares should remain unchanged
-                        let receiver_shares: i128 = read_total_shares_of(&env, receiver.clone());
-                        write_total_shares_of(
-                            &env,
-                            owner.clone(),
-                            &safe_sub_i128(owner_shares, shares_amount),
-                        );
-                        write_total_shares_of(
-                            &env,
-                            receiver.clone(),
-                            &safe_add_i128(receiver_shares, shares_amount),
-                        );
-                        Self::_emit_transfer_shares_event(&env, &owner, &receiver, shares_amount);
-                        Ok(true)
-                    }
-                }
+                _increase_allowance(&env, &owner, &spender, amount)
             }
         } else {
             Err(VaultError::AdministratorError)
         }
     }
 
-    fn approve_asset_allowance(
+    fn decrease_allowance(
         env: Env,
-        asset_address: Address,
+        owner: Address,
         spender: Address,
-        approve_amount: i128,
-        expiration_ledger: u32,
-    ) -> Result<(), VaultError> {
+        amount: i128,
+    ) -> Result<i128, VaultError> {
+        if has_administrator(&env) {
+            owner.require_auth();
+            if amount <= 0 {
+                Err(VaultError::InvalidAmount)
+            } else {
+                _decrease_allowance(&env, &owner, &spender, amount)
+            }
+        } else {
+            Err(VaultError::AdministratorError)
+        }
+    }
+
+    fn revoke_allowance(env: Env, owner: Address, spender: Address) -> Result<bool, VaultError> {
+        if has_administrator(&env) {
+            owner.require_auth();
+            if _read_live_allowance(&env, &owner, &spender).is_none() {
+                Err(VaultError::NoAllowance)
+            } else {
+                _approve_allowance(&env, &owner, &spender, 0, 0)?;
+                Ok(true)
+            }
+        } else {
+            Err(VaultError::AdministratorError)
+        }
+    }
+
+    fn permit_signer(env: Env, owner: Address) -> Option<BytesN<32>> {
+        read_permit_signer(&env, owner)
+    }
+
+    fn set_permit_signer(
+        env: Env,
+        owner: Address,
+        public_key: Option<BytesN<32>>,
+    ) -> Result<bool, VaultError> {
+        // The ed25519 key that signs permits on behalf of the owner, none disables permits
+        if has_administrator(&env) {
+            owner.require_auth();
+            match public_key.clone() {
+                Some(public_key) => write_permit_signer(&env, owner.clone(), &public_key),
+                None => remove_permit_signer(&env, owner.clone()),
+            }
+            Self::_emit_permit_signer_event(&env, &owner, public_key);
+            Ok(true)
+        } else {
+            Err(VaultError::AdministratorError)
+        }
+    }
+
+    fn nonces(env: Env, owner: Address) -> u64 {
+        read_permit_nonce(&env, owner)
+    }
+
+    fn permit(
+        env: Env,
+        owner: Address,
+        spender: Address,
+        amount: i128,
+        expiration_ledger: u32,
+        deadline: u64,
+        signature: BytesN<64>,
+    ) -> Result<bool, VaultError> {
+        // Anyone can submit the signed approval, e.g. a relayer that spends it in the same transaction.
+        // An invalid signature fails in the host function and reverts the whole invocation.
+        if has_administrator(&env) {
+            Self::_ensure_before_deadline(&env, deadline)?;
+            if amount < 0 {
+                return Err(VaultError::InvalidAmount);
+            }
+            if owner == spender {
+                return Err(VaultError::CannotApproveOrTransferToSelf);
+            }
+            let public_key: BytesN<32> =
+                read_permit_signer(&env, owner.clone()).ok_or(VaultError::PermitSignerNotSet)?;
+            let nonce: u64 = read_permit_nonce(&env, owner.clone());
+            let message = PermitMessage {
+                vault: Self::contract_address(&env),
+                owner: owner.clone(),
+                spender: spender.clone(),
+                amount,
+                expiration_ledger,
+                nonce,
+                deadline,
+            };
+            env.crypto()
+                .ed25519_verify(&public_key, &message.to_xdr(&env), &signature);
+            write_permit_nonce(&env, owner.clone(), &(nonce + 1));
+            _approve_allowance(&env, &owner, &spender, amount, expiration_ledger)?;
+            Ok(true)
+        } else {
+            Err(VaultError::AdministratorError)
+        }
+    }
+
+    fn transfer_shares(
+        env: Env,
+        owner: Address,
+        receiver: Address,
+        shares_amount: i128,
+    ) -> Result<bool, VaultError> {
+        if has_administrator(&env) {
+            owner.require_auth();
+            Self::_accrue_fees(&env);
+            if shares_amount <= 0 {
+                Err(VaultError::InvalidAmount)
+            } else {
+                if owner == receiver {
+                    Err(VaultError::CannotApproveOrTransferToSelf)
+                } else {
+                    Self::_transfer_shares(&env, &owner, &receiver, shares_amount)?;
+                    Ok(true)
+                }
+            }
+        } else {
+            Err(VaultError::AdministratorError)
+        }
+    }
+
+    fn transfer_shares_from(
+        env: Env,
+        spender: Address,
+        owner: Address,
+        receiver: Address,
+        shares_amount: i128,
+    ) -> Result<bool, VaultError> {
+        if has_administrator(&env) {
+            spender.require_auth();
+            Self::_accrue_fees(&env);
+            if shares_amount <= 0 {
+                Err(VaultError::InvalidAmount)
+            } else if owner == receiver || owner == spender {
+                Err(VaultError::CannotApproveOrTransferToSelf)
+            } else {
+                Self::_ensure_not_frozen(&env, &spender)?;
+                // Fails if there is no allowance, it has expired or it is too small
+                _spend_allowance(&env, &owner, &spender, shares_amount)?;
+                Self::_transfer_shares(&env, &owner, &receiver, shares_amount)?;
+                Ok(true)
+            }
+        } else {
+            Err(VaultError::AdministratorError)
+        }
+    }
+
+    fn approve_asset_allowance(
+        env: Env,
+        asset_address: Address,
+        spender: Address,
+        approve_amount: i128,
+        expiration_ledger: u32,
+    ) -> Result<(), VaultError> {
         // Contracts can approve token allowances without explicit require_auth() when they are acting on their own behalf.
         // The contract's address itself implies authorization.
         // However, admin or similar access control is still needed to prevent unauthorized approvals.
@@ -487,9 +1198,20 @@ impl IPublicVault for Vault {
     }
 
     fn pause(env: Env) -> Result<bool, ContractError> {
+        // The owner does not name itself here, pausers and guardians call pause_by
         if has_administrator(&env) {
             let admin: Address = read_administrator(&env);
-            admin.require_auth();
+            Self::pause_by(env, admin)
+        } else {
+            Err(ContractError::NotInitialized)
+        }
+    }
+
+    fn pause_by(env: Env, caller: Address) -> Result<bool, ContractError> {
+        if has_administrator(&env) {
+            if !Self::_authorize(&env, &caller, &[Role::Owner, Role::Pauser, Role::Guardian]) {
+                return Err(ContractError::Unauthorized);
+            }
             if is_paused(&env) {
                 Err(ContractError::ContractIsAlreadyPaused)
             } else {
@@ -504,7 +1226,17 @@ impl IPublicVault for Vault {
     fn unpause(env: Env) -> Result<bool, ContractError> {
         if has_administrator(&env) {
             let admin: Address = read_administrator(&env);
-            admin.require_auth();
+            Self::unpause_by(env, admin)
+        } else {
+            Err(ContractError::NotInitialized)
+        }
+    }
+
+    fn unpause_by(env: Env, caller: Address) -> Result<bool, ContractError> {
+        if has_administrator(&env) {
+            if !Self::_authorize(&env, &caller, &[Role::Owner, Role::Pauser]) {
+                return Err(ContractError::Unauthorized);
+            }
             if is_paused(&env) {
                 remove_paused(&env);
                 remove_deposit_paused(&env);
@@ -521,7 +1253,17 @@ impl IPublicVault for Vault {
     fn pause_deposit(env: Env) -> Result<bool, ContractError> {
         if has_administrator(&env) {
             let admin: Address = read_administrator(&env);
-            admin.require_auth();
+            Self::pause_deposit_by(env, admin)
+        } else {
+            Err(ContractError::NotInitialized)
+        }
+    }
+
+    fn pause_deposit_by(env: Env, caller: Address) -> Result<bool, ContractError> {
+        if has_administrator(&env) {
+            if !Self::_authorize(&env, &caller, &[Role::Owner, Role::Pauser, Role::Guardian]) {
+                return Err(ContractError::Unauthorized);
+            }
             if is_paused(&env) {
                 Err(ContractError::ContractIsAlreadyPaused)
             } else if deposit_paused(&env) {
@@ -538,7 +1280,17 @@ impl IPublicVault for Vault {
     fn pause_withdrawal(env: Env) -> Result<bool, ContractError> {
         if has_administrator(&env) {
             let admin: Address = read_administrator(&env);
-            admin.require_auth();
+            Self::pause_withdrawal_by(env, admin)
+        } else {
+            Err(ContractError::NotInitialized)
+        }
+    }
+
+    fn pause_withdrawal_by(env: Env, caller: Address) -> Result<bool, ContractError> {
+        if has_administrator(&env) {
+            if !Self::_authorize(&env, &caller, &[Role::Owner, Role::Pauser, Role::Guardian]) {
+                return Err(ContractError::Unauthorized);
+            }
             if is_paused(&env) {
                 Err(ContractError::ContractIsAlreadyPaused)
             } else if withdraw_paused(&env) {
@@ -555,7 +1307,17 @@ impl IPublicVault for Vault {
     fn unpause_deposit(env: Env) -> Result<bool, ContractError> {
         if has_administrator(&env) {
             let admin: Address = read_administrator(&env);
-            admin.require_auth();
+            Self::unpause_deposit_by(env, admin)
+        } else {
+            Err(ContractError::NotInitialized)
+        }
+    }
+
+    fn unpause_deposit_by(env: Env, caller: Address) -> Result<bool, ContractError> {
+        if has_administrator(&env) {
+            if !Self::_authorize(&env, &caller, &[Role::Owner, Role::Pauser]) {
+                return Err(ContractError::Unauthorized);
+            }
             if is_paused(&env) && deposit_paused(&env) {
                 write_withdraw_paused(&env);
                 remove_deposit_paused(&env);
@@ -572,7 +1334,17 @@ impl IPublicVault for Vault {
     fn unpause_withdrawal(env: Env) -> Result<bool, ContractError> {
         if has_administrator(&env) {
             let admin: Address = read_administrator(&env);
-            admin.require_auth();
+            Self::unpause_withdrawal_by(env, admin)
+        } else {
+            Err(ContractError::NotInitialized)
+        }
+    }
+
+    fn unpause_withdrawal_by(env: Env, caller: Address) -> Result<bool, ContractError> {
+        if has_administrator(&env) {
+            if !Self::_authorize(&env, &caller, &[Role::Owner, Role::Pauser]) {
+                return Err(ContractError::Unauthorized);
+            }
             if is_paused(&env) && withdraw_paused(&env) {
                 write_deposit_paused(&env);
                 remove_withdraw_paused(&env);
@@ -586,6 +1358,64 @@ impl IPublicVault for Vault {
         }
     }
 
+    fn is_frozen(env: Env, address: Address) -> bool {
+        is_frozen(&env, address)
+    }
+
+    fn freeze(env: Env, address: Address) -> Result<bool, ContractError> {
+        // A frozen account can not deposit, withdraw, redeem or move shares, the rest of the vault keeps running
+        if has_administrator(&env) {
+            let admin: Address = read_administrator(&env);
+            Self::freeze_by(env, admin, address)
+        } else {
+            Err(ContractError::NotInitialized)
+        }
+    }
+
+    fn freeze_by(env: Env, caller: Address, address: Address) -> Result<bool, ContractError> {
+        // The owner and compliance accounts can freeze
+        if has_administrator(&env) {
+            if !Self::_authorize(&env, &caller, &[Role::Owner, Role::Compliance]) {
+                return Err(ContractError::Unauthorized);
+            }
+            if is_frozen(&env, address.clone()) {
+                Err(ContractError::AccountIsAlreadyFrozen)
+            } else {
+                write_frozen(&env, address.clone());
+                Self::_emit_freeze_event(&env, &caller, &address, true);
+                Ok(true)
+            }
+        } else {
+            Err(ContractError::NotInitialized)
+        }
+    }
+
+    fn unfreeze(env: Env, address: Address) -> Result<bool, ContractError> {
+        if has_administrator(&env) {
+            let admin: Address = read_administrator(&env);
+            Self::unfreeze_by(env, admin, address)
+        } else {
+            Err(ContractError::NotInitialized)
+        }
+    }
+
+    fn unfreeze_by(env: Env, caller: Address, address: Address) -> Result<bool, ContractError> {
+        if has_administrator(&env) {
+            if !Self::_authorize(&env, &caller, &[Role::Owner, Role::Compliance]) {
+                return Err(ContractError::Unauthorized);
+            }
+            if is_frozen(&env, address.clone()) {
+                remove_frozen(&env, address.clone());
+                Self::_emit_freeze_event(&env, &caller, &address, false);
+                Ok(true)
+            } else {
+                Err(ContractError::AccountIsAlreadyNotFrozen)
+            }
+        } else {
+            Err(ContractError::NotInitialized)
+        }
+    }
+
     fn extend_vault_ttl(env: &Env) -> Result<bool, ContractError> {
         // Anyone can call this function to extend time-to-live
         if has_administrator(&env) {
@@ -596,203 +1426,1349 @@ impl IPublicVault for Vault {
             Err(ContractError::NotInitialized)
         }
     }
-}
 
-// Private functions
-#[allow(dead_code)]
-impl Vault {
-    fn _transfer(
-        env: Env,
-        from: Address,
-        to: Address,
-        amount: i128,
-    ) -> Result<(Address, Address, i128, i128), VaultError> {
-        from.require_auth();
-        if amount <= 0 {
-            Err(VaultError::InvalidAmount)
+    fn extend_shares_ttl(env: &Env, address: Address) -> Result<bool, ContractError> {
+        // Anyone can call this function to keep a holder's balance entry alive
+        // Returns false when the address holds no shares, as there is nothing to extend
+        if has_administrator(env) {
+            Ok(extend_shares_of_ttl(
+                env,
+                address,
+                BUMP_THRESHOLD,
+                EXTEND_TO_DAYS,
+            ))
         } else {
-            let result: i128 = Self::_multiply_by_decimals(&env, amount);
-            let asset_address: Address = read_asset_address(&env);
-            let token_client = token::Client::new(&env, &asset_address);
-            token_client.transfer(&from, &to, &result);
+            Err(ContractError::NotInitialized)
+        }
+    }
 
-            Ok((from, to, amount, result))
+    fn upgrade(env: Env, new_wasm_hash: BytesN<32>) -> Result<bool, ContractError> {
+        // Replaces the contract code, storage is kept and converted afterwards by calling migrate
+        if has_administrator(&env) {
+            let admin: Address = read_administrator(&env);
+            admin.require_auth();
+            env.deployer()
+                .update_current_contract_wasm(new_wasm_hash.clone());
+            Self::_emit_upgraded_event(&env, &admin, new_wasm_hash);
+            Ok(true)
+        } else {
+            Err(ContractError::NotInitialized)
         }
     }
 
-    fn _multiply_by_decimals(env: &Env, amount: i128) -> i128 {
-        let decimals: u32 = read_asset_decimals(&env);
-        let result_pow: i128 = safe_pow(10_i128, decimals);
-        let result: i128 = safe_mul(amount, result_pow);
-        result
+    fn schema_version(env: Env) -> u32 {
+        read_schema_version(&env)
+    }
+
+    fn migrate(env: Env) -> Result<u32, ContractError> {
+        // Converts storage written by earlier versions one step at a time, up to the current layout
+        if has_administrator(&env) {
+            let admin: Address = read_administrator(&env);
+            admin.require_auth();
+            let previous: u32 = read_schema_version(&env);
+            if previous >= SCHEMA_VERSION {
+                return Err(ContractError::AlreadyMigrated);
+            }
+            for version in previous..SCHEMA_VERSION {
+                Self::_migrate_from(&env, version);
+            }
+            write_schema_version(&env, &SCHEMA_VERSION);
+            Self::_emit_schema_migrated_event(&env, &admin, previous, SCHEMA_VERSION);
+            Ok(SCHEMA_VERSION)
+        } else {
+            Err(ContractError::NotInitialized)
+        }
     }
 
-    fn _divide_by_decimals(env: &Env, amount: i128) -> i128 {
-        let decimals: u32 = read_asset_decimals(&env);
-        let result_pow: i128 = safe_pow(10_i128, decimals);
-        let result: i128 = safe_div(amount, result_pow);
-        result
+    fn uses_base_units(env: Env) -> bool {
+        has_base_unit_accounting(&env)
     }
 
-    fn _convert_to_shares(
-        env: &Env,
-        assets: i128,
-        rounding: Rounding,
-    ) -> Result<i128, ContractError> {
-        if assets <= 0 {
-            Ok(0) // Assume it is fine to return zero here
+    fn migrate_to_base_units(env: Env, holders: Vec<Address>) -> Result<i128, ContractError> {
+        // Vaults deployed before base unit accounting minted shares for whole tokens only.
+        // Scaling every balance by 10^decimals keeps each holder's claim on the assets unchanged,
+        // while new deposits are no longer rounded down to whole tokens.
+        // The holder list must cover all outstanding shares, otherwise the migration is rejected.
+        // Allowances can not be enumerated and are left unscaled, so an allowance of n shares
+        // now covers n base units only. Owners have to approve their spenders again.
+        if has_administrator(&env) {
+            let admin: Address = read_administrator(&env);
+            admin.require_auth();
+            if has_base_unit_accounting(&env) {
+                return Err(ContractError::AlreadyMigrated);
+            }
+            let mut balances: Map<Address, i128> = Map::new(&env);
+            let mut listed_shares: i128 = 0;
+            for holder in holders.iter() {
+                if !balances.contains_key(holder.clone()) {
+                    let balance: i128 = read_total_shares_of(&env, holder.clone());
+                    listed_shares = safe_add_i128(listed_shares, balance);
+                    balances.set(holder, balance);
+                }
+            }
+            let total_shares: i128 = read_total_shares(&env);
+            if listed_shares != total_shares {
+                return Err(ContractError::IncompleteHolderList);
+            }
+            let scale: i128 = safe_pow(10_i128, read_asset_decimals(&env));
+            for (holder, balance) in balances.iter() {
+                Self::_write_shares_of(&env, &holder, safe_mul(balance, scale));
+            }
+            let new_total_shares: i128 = safe_mul(total_shares, scale);
+            write_total_shares(&env, &new_total_shares);
+            write_base_unit_accounting(&env);
+            Self::_emit_base_units_migrated_event(&env, &admin, scale, new_total_shares);
+            Ok(new_total_shares)
         } else {
-            let tot_shares: i128 = Self::total_shares(env)?;
-            let tot_assets: i128 = Self::total_assets(env)?;
-            let result: i128 = mul_div(
-                assets,
-                safe_add_i128(tot_shares, safe_pow(10, Self::_decimals_offset())),
-                safe_add_i128(tot_assets, 1),
-                rounding,
-            );
-            Ok(result)
+            Err(ContractError::NotInitialized)
         }
     }
 
-    // Called to calculate potential return of value
-    fn _convert_to_shares_simulate(
-        assets: i128,
-        total_assets: i128,
-        total_shares: i128,
-        rounding: Rounding,
-    ) -> Result<i128, ContractError> {
-        if assets <= 0 || total_assets <= assets || total_shares <= 0 {
-            Ok(0) // Assume it is fine to return zero here
+    fn untracked_assets(env: &Env) -> Result<i128, ContractError> {
+        // Positive when tokens were sent directly to the vault, negative if the balance fell below the tracked assets
+        if has_administrator(env) {
+            let balance: i128 = Self::_asset_balance(env);
+            Ok(safe_sub_i128(balance, Self::_managed_assets(env)))
         } else {
-            let result: i128 = mul_div(
-                assets,
-                safe_add_i128(total_shares, safe_pow(10, Self::_decimals_offset())),
-                safe_add_i128(total_assets, 1),
-                rounding,
-            );
-            Ok(result)
+            Err(ContractError::NotInitialized)
         }
     }
 
-    fn _convert_to_assets(
+    fn sync(env: Env) -> Result<i128, ContractError> {
+        // Accept the actual token balance as the vault's assets, e.g. after a shortfall or for a vault
+        // deployed before internal accounting. Any surplus is distributed to the current share holders.
+        if has_administrator(&env) {
+            let admin: Address = read_administrator(&env);
+            admin.require_auth();
+            Self::_accrue_fees(&env);
+            let previous: i128 = Self::_managed_assets(&env);
+            let balance: i128 = Self::_asset_balance(&env);
+            write_total_managed_assets(&env, &balance);
+            Self::_emit_sync_event(&env, &admin, previous, balance);
+            Ok(balance)
+        } else {
+            Err(ContractError::NotInitialized)
+        }
+    }
+
+    fn skim(env: Env, to: Address) -> Result<i128, ContractError> {
+        // Sweep tokens that the vault holds on top of its tracked assets
+        if has_administrator(&env) {
+            let admin: Address = read_administrator(&env);
+            admin.require_auth();
+            let surplus: i128 =
+                safe_sub_i128(Self::_asset_balance(&env), Self::_managed_assets(&env));
+            if surplus > 0 {
+                let token_client = token::Client::new(&env, &read_asset_address(&env));
+                token_client.transfer(&Self::contract_address(&env), &to, &surplus);
+                Self::_emit_skim_event(&env, &to, surplus);
+                Ok(surplus)
+            } else {
+                Ok(0)
+            }
+        } else {
+            Err(ContractError::NotInitialized)
+        }
+    }
+
+    fn market_address(env: &Env) -> Result<Address, ContractError> {
+        if has_administrator(env) {
+            read_market_address(env).ok_or(ContractError::MarketNotSet)
+        } else {
+            Err(ContractError::NotInitialized)
+        }
+    }
+
+    fn set_market(env: Env, market: Address) -> Result<bool, ContractError> {
+        // The market moves collateral between its vaults on settlement, it is granted the market operator role
+        if has_administrator(&env) {
+            let admin: Address = read_administrator(&env);
+            admin.require_auth();
+            let previous: Option<Address> = read_market_address(&env);
+            Self::_replace_role_holder(
+                &env,
+                &admin,
+                Role::MarketOperator,
+                previous,
+                Some(market.clone()),
+            );
+            write_market_address(&env, &market);
+            Ok(true)
+        } else {
+            Err(ContractError::NotInitialized)
+        }
+    }
+
+    fn deposit_limits(env: &Env) -> Result<DepositLimits, ContractError> {
+        if has_administrator(env) {
+            Ok(DepositLimits {
+                total_assets_cap: read_total_assets_cap(env),
+                account_cap: read_account_deposit_cap(env),
+                min_deposit: read_min_deposit(env),
+            })
+        } else {
+            Err(ContractError::NotInitialized)
+        }
+    }
+
+    fn set_deposit_limits(
+        env: Env,
+        total_assets_cap: Option<i128>,
+        account_cap: Option<i128>,
+        min_deposit: Option<i128>,
+    ) -> Result<bool, ContractError> {
+        // None removes a limit, caps apply to assets and do not affect existing positions.
+        // The account cap is only checked on deposit, it does not follow share transfers.
+        if has_administrator(&env) {
+            let admin: Address = read_administrator(&env);
+            admin.require_auth();
+            let min: i128 = min_deposit.unwrap_or(1);
+            if min <= 0
+                || total_assets_cap.is_some_and(|cap| cap < min)
+                || account_cap.is_some_and(|cap| cap < min)
+            {
+                return Err(ContractError::InvalidDepositLimits);
+            }
+            write_total_assets_cap(&env, &total_assets_cap);
+            write_account_deposit_cap(&env, &account_cap);
+            write_min_deposit(&env, &min_deposit);
+            Self::_emit_deposit_limits_event(
+                &env,
+                &admin,
+                total_assets_cap,
+                account_cap,
+                min_deposit,
+            );
+            Ok(true)
+        } else {
+            Err(ContractError::NotInitialized)
+        }
+    }
+
+    fn fees(env: &Env) -> Result<FeeConfig, ContractError> {
+        if has_administrator(env) {
+            Ok(FeeConfig {
+                entry_fee_basis_points: read_entry_fee_basis_points(env),
+                exit_fee_basis_points: read_exit_fee_basis_points(env),
+                recipient: read_fee_recipient(env),
+                management_fee_basis_points: read_management_fee_basis_points(env),
+                performance_fee_basis_points: read_performance_fee_basis_points(env),
+                treasury: read_treasury(env),
+            })
+        } else {
+            Err(ContractError::NotInitialized)
+        }
+    }
+
+    fn set_fees(
+        env: Env,
+        entry_fee_basis_points: u32,
+        exit_fee_basis_points: u32,
+        recipient: Address,
+    ) -> Result<bool, ContractError> {
+        if has_administrator(&env) {
+            let admin: Address = read_administrator(&env);
+            Self::set_fees_by(
+                env,
+                admin,
+                entry_fee_basis_points,
+                exit_fee_basis_points,
+                recipient,
+            )
+        } else {
+            Err(ContractError::NotInitialized)
+        }
+    }
+
+    fn set_fees_by(
+        env: Env,
+        caller: Address,
+        entry_fee_basis_points: u32,
+        exit_fee_basis_points: u32,
+        recipient: Address,
+    ) -> Result<bool, ContractError> {
+        if has_administrator(&env) {
+            if !Self::_authorize(&env, &caller, &[Role::Owner, Role::FeeManager]) {
+                return Err(ContractError::Unauthorized);
+            }
+            if entry_fee_basis_points > MAX_FEE_BASIS_POINTS
+                || exit_fee_basis_points > MAX_FEE_BASIS_POINTS
+                || recipient == Self::contract_address(&env)
+            {
+                Err(ContractError::InvalidFee)
+            } else {
+                write_entry_fee_basis_points(&env, &entry_fee_basis_points);
+                write_exit_fee_basis_points(&env, &exit_fee_basis_points);
+                write_fee_recipient(&env, &recipient);
+                Self::_emit_fees_updated_event(
+                    &env,
+                    &caller,
+                    &recipient,
+                    entry_fee_basis_points,
+                    exit_fee_basis_points,
+                );
+                Ok(true)
+            }
+        } else {
+            Err(ContractError::NotInitialized)
+        }
+    }
+
+    fn set_accrual_fees(
+        env: Env,
+        management_fee_basis_points: u32,
+        performance_fee_basis_points: u32,
+        treasury: Address,
+    ) -> Result<bool, ContractError> {
+        if has_administrator(&env) {
+            let admin: Address = read_administrator(&env);
+            Self::set_accrual_fees_by(
+                env,
+                admin,
+                management_fee_basis_points,
+                performance_fee_basis_points,
+                treasury,
+            )
+        } else {
+            Err(ContractError::NotInitialized)
+        }
+    }
+
+    fn set_accrual_fees_by(
+        env: Env,
+        caller: Address,
+        management_fee_basis_points: u32,
+        performance_fee_basis_points: u32,
+        treasury: Address,
+    ) -> Result<bool, ContractError> {
+        if has_administrator(&env) {
+            if !Self::_authorize(&env, &caller, &[Role::Owner, Role::FeeManager]) {
+                return Err(ContractError::Unauthorized);
+            }
+            if management_fee_basis_points > MAX_MANAGEMENT_FEE_BASIS_POINTS
+                || performance_fee_basis_points > MAX_PERFORMANCE_FEE_BASIS_POINTS
+                || treasury == Self::contract_address(&env)
+            {
+                Err(ContractError::InvalidFee)
+            } else {
+                // Fees accrued so far are charged with the previous configuration
+                Self::_accrue_fees(&env);
+                write_management_fee_basis_points(&env, &management_fee_basis_points);
+                write_performance_fee_basis_points(&env, &performance_fee_basis_points);
+                write_treasury(&env, &treasury);
+                Self::_emit_accrual_fees_updated_event(
+                    &env,
+                    &caller,
+                    &treasury,
+                    management_fee_basis_points,
+                    performance_fee_basis_points,
+                );
+                Ok(true)
+            }
+        } else {
+            Err(ContractError::NotInitialized)
+        }
+    }
+
+    fn accrue_fees(env: Env) -> Result<i128, ContractError> {
+        // Anyone can call this function, fees are also accrued before every state-changing call
+        if has_administrator(&env) {
+            Ok(Self::_accrue_fees(&env))
+        } else {
+            Err(ContractError::NotInitialized)
+        }
+    }
+
+    fn pending_fees(env: &Env) -> Result<i128, ContractError> {
+        // Shares that the next accrual would mint to the treasury
+        if has_administrator(env) {
+            let (_, _, shares) = Self::_pending_fee_accrual(env);
+            Ok(shares)
+        } else {
+            Err(ContractError::NotInitialized)
+        }
+    }
+
+    fn high_water_mark(env: &Env) -> Result<i128, ContractError> {
+        // Share price scaled by 1e18 above which performance fees are charged
+        if has_administrator(env) {
+            Ok(read_high_water_mark(env).unwrap_or_else(|| Self::_current_share_price(env)))
+        } else {
+            Err(ContractError::NotInitialized)
+        }
+    }
+
+    fn compliance_address(env: &Env) -> Result<Option<Address>, ContractError> {
+        if has_administrator(env) {
+            Ok(read_compliance_address(env))
+        } else {
+            Err(ContractError::NotInitialized)
+        }
+    }
+
+    fn set_compliance(env: Env, compliance: Option<Address>) -> Result<bool, ContractError> {
+        // Grants the compliance role to a single account, None revokes it from the previous one
+        if has_administrator(&env) {
+            let admin: Address = read_administrator(&env);
+            admin.require_auth();
+            let previous: Option<Address> = read_compliance_address(&env);
+            Self::_replace_role_holder(
+                &env,
+                &admin,
+                Role::Compliance,
+                previous,
+                compliance.clone(),
+            );
+            write_compliance_address(&env, &compliance);
+            Self::_emit_compliance_event(&env, &admin, compliance);
+            Ok(true)
+        } else {
+            Err(ContractError::NotInitialized)
+        }
+    }
+
+    fn is_allowlist_enabled(env: Env) -> bool {
+        allowlist_enabled(&env)
+    }
+
+    fn set_allowlist_enabled(env: Env, enabled: bool) -> Result<bool, ContractError> {
+        if has_administrator(&env) {
+            let admin: Address = read_administrator(&env);
+            admin.require_auth();
+            if enabled {
+                write_allowlist_enabled(&env);
+            } else {
+                remove_allowlist_enabled(&env);
+            }
+            Self::_emit_allowlist_mode_event(&env, &admin, enabled);
+            Ok(true)
+        } else {
+            Err(ContractError::NotInitialized)
+        }
+    }
+
+    fn is_allowlisted(env: Env, address: Address) -> bool {
+        is_allowlisted(&env, address)
+    }
+
+    fn add_to_allowlist(
+        env: Env,
+        caller: Address,
+        addresses: Vec<Address>,
+    ) -> Result<u32, VaultError> {
+        if has_administrator(&env) {
+            Self::_require_compliance(&env, &caller)?;
+            let mut added: u32 = 0;
+            for address in addresses.iter() {
+                if !is_allowlisted(&env, address.clone()) {
+                    write_allowlisted(&env, address.clone());
+                    Self::_emit_allowlisted_event(&env, &caller, &address, true);
+                    added = safe_add_u32(added, 1);
+                }
+            }
+            Ok(added)
+        } else {
+            Err(VaultError::AdministratorError)
+        }
+    }
+
+    fn remove_from_allowlist(
+        env: Env,
+        caller: Address,
+        addresses: Vec<Address>,
+    ) -> Result<u32, VaultError> {
+        // Removed addresses keep their shares, they can still withdraw but not receive new ones
+        if has_administrator(&env) {
+            Self::_require_compliance(&env, &caller)?;
+            let mut removed: u32 = 0;
+            for address in addresses.iter() {
+                if is_allowlisted(&env, address.clone()) {
+                    remove_allowlisted(&env, address.clone());
+                    Self::_emit_allowlisted_event(&env, &caller, &address, false);
+                    removed = safe_add_u32(removed, 1);
+                }
+            }
+            Ok(removed)
+        } else {
+            Err(VaultError::AdministratorError)
+        }
+    }
+
+    fn settle_out(env: Env, to: Address, amount: i128) -> Result<i128, VaultError> {
+        // Called by the market registered with set_market to pay out collateral or commission on settlement
+        if has_administrator(&env) {
+            // The market must still hold the market operator role
+            let market: Address = read_market_address(&env).ok_or(VaultError::MarketNotSet)?;
+            Self::settle_out_by(env, market, to, amount)
+        } else {
+            Err(VaultError::AdministratorError)
+        }
+    }
+
+    fn settle_out_by(
+        env: Env,
+        caller: Address,
+        to: Address,
+        amount: i128,
+    ) -> Result<i128, VaultError> {
+        if has_administrator(&env) {
+            if !Self::_authorize(&env, &caller, &[Role::MarketOperator]) {
+                return Err(VaultError::Unauthorized);
+            }
+            Self::_settle_out(&env, &caller, &to, amount)
+        } else {
+            Err(VaultError::AdministratorError)
+        }
+    }
+
+    fn settle_in(env: Env, amount: i128) -> Result<i128, VaultError> {
+        // Called by the registered market after it transferred collateral into this vault
+        if has_administrator(&env) {
+            let market: Address = read_market_address(&env).ok_or(VaultError::MarketNotSet)?;
+            Self::settle_in_by(env, market, amount)
+        } else {
+            Err(VaultError::AdministratorError)
+        }
+    }
+
+    fn settle_in_by(env: Env, caller: Address, amount: i128) -> Result<i128, VaultError> {
+        if has_administrator(&env) {
+            if !Self::_authorize(&env, &caller, &[Role::MarketOperator]) {
+                return Err(VaultError::Unauthorized);
+            }
+            Self::_settle_in(&env, &caller, amount)
+        } else {
+            Err(VaultError::AdministratorError)
+        }
+    }
+
+    fn has_role(env: Env, role: Role, account: Address) -> bool {
+        has_administrator(&env) && Self::_has_role(&env, role, &account)
+    }
+
+    fn roles_of(env: Env, account: Address) -> Vec<Role> {
+        let mut roles: Vec<Role> = Vec::new(&env);
+        if has_administrator(&env) {
+            for role in ALL_ROLES {
+                if Self::_has_role(&env, role, &account) {
+                    roles.push_back(role);
+                }
+            }
+        }
+        roles
+    }
+
+    fn grant_role(env: Env, role: Role, account: Address) -> Result<bool, ContractError> {
+        // Only the owner grants roles, ownership itself is not granted here
+        if has_administrator(&env) {
+            let admin: Address = read_administrator(&env);
+            admin.require_auth();
+            if role == Role::Owner {
+                Err(ContractError::InvalidRole)
+            } else if has_role(&env, role, account.clone()) {
+                Err(ContractError::RoleIsAlreadyGranted)
+            } else {
+                write_role(&env, role, account.clone());
+                Self::_emit_role_granted_event(&env, &admin, role, &account);
+                Ok(true)
+            }
+        } else {
+            Err(ContractError::NotInitialized)
+        }
+    }
+
+    fn revoke_role(env: Env, role: Role, account: Address) -> Result<bool, ContractError> {
+        if has_administrator(&env) {
+            let admin: Address = read_administrator(&env);
+            admin.require_auth();
+            if role == Role::Owner {
+                Err(ContractError::InvalidRole)
+            } else if !has_role(&env, role, account.clone()) {
+                Err(ContractError::RoleIsAlreadyNotGranted)
+            } else {
+                remove_role(&env, role, account.clone());
+                Self::_emit_role_revoked_event(&env, &admin, role, &account);
+                Ok(true)
+            }
+        } else {
+            Err(ContractError::NotInitialized)
+        }
+    }
+}
+
+// Token interface (SEP-41), vault shares can be held and moved like any other Soroban token
+#[cfg_attr(
+    not(all(feature = "library", target_family = "wasm")),
+    soroban_sdk::contractimpl
+)]
+impl TokenInterface for Vault {
+    fn allowance(env: Env, from: Address, spender: Address) -> i128 {
+        Self::_ensure_initialized(&env);
+        _read_allowance_amount(&env, &from, &spender)
+    }
+
+    fn approve(env: Env, from: Address, spender: Address, amount: i128, expiration_ledger: u32) {
+        Self::_ensure_initialized(&env);
+        from.require_auth();
+        if amount < 0 {
+            panic_with_error!(&env, VaultError::InvalidAmount);
+        }
+        if from == spender {
+            panic_with_error!(&env, VaultError::CannotApproveOrTransferToSelf);
+        }
+        Self::_unwrap_or_panic(
+            &env,
+            _approve_allowance(&env, &from, &spender, amount, expiration_ledger),
+        );
+    }
+
+    fn balance(env: Env, id: Address) -> i128 {
+        Self::_ensure_initialized(&env);
+        read_total_shares_of(&env, id)
+    }
+
+    fn transfer(env: Env, from: Address, to: Address, amount: i128) {
+        Self::_ensure_initialized(&env);
+        from.require_auth();
+        Self::_accrue_fees(&env);
+        Self::_unwrap_or_panic(&env, Self::_transfer_shares(&env, &from, &to, amount));
+    }
+
+    fn transfer_from(env: Env, spender: Address, from: Address, to: Address, amount: i128) {
+        Self::_ensure_initialized(&env);
+        spender.require_auth();
+        Self::_accrue_fees(&env);
+        Self::_unwrap_or_panic(&env, Self::_ensure_not_frozen(&env, &spender));
+        Self::_unwrap_or_panic(&env, _spend_allowance(&env, &from, &spender, amount));
+        Self::_unwrap_or_panic(&env, Self::_transfer_shares(&env, &from, &to, amount));
+    }
+
+    fn burn(env: Env, from: Address, amount: i128) {
+        Self::_ensure_initialized(&env);
+        from.require_auth();
+        Self::_accrue_fees(&env);
+        Self::_unwrap_or_panic(&env, Self::_burn(&env, &from, amount));
+    }
+
+    fn burn_from(env: Env, spender: Address, from: Address, amount: i128) {
+        Self::_ensure_initialized(&env);
+        spender.require_auth();
+        Self::_accrue_fees(&env);
+        Self::_unwrap_or_panic(&env, Self::_ensure_not_frozen(&env, &spender));
+        Self::_unwrap_or_panic(&env, _spend_allowance(&env, &from, &spender, amount));
+        Self::_unwrap_or_panic(&env, Self::_burn(&env, &from, amount));
+    }
+
+    fn decimals(env: Env) -> u32 {
+        Self::_unwrap_or_panic(&env, Self::asset_decimals(&env))
+    }
+
+    fn name(env: Env) -> String {
+        let asset_name: String = Self::_unwrap_or_panic(&env, Self::asset_name(&env));
+        Self::_prefixed_string(&env, SHARE_NAME_PREFIX, &asset_name)
+    }
+
+    fn symbol(env: Env) -> String {
+        let asset_symbol: String = Self::_unwrap_or_panic(&env, Self::asset_symbol(&env));
+        Self::_prefixed_string(&env, SHARE_SYMBOL_PREFIX, &asset_symbol)
+    }
+}
+
+// Private functions
+#[allow(dead_code)]
+impl Vault {
+    fn _transfer(
+        env: Env,
+        from: Address,
+        to: Address,
+        amount: i128,
+    ) -> Result<(Address, Address, i128), VaultError> {
+        from.require_auth();
+        if amount <= 0 {
+            Err(VaultError::InvalidAmount)
+        } else {
+            let asset_address: Address = read_asset_address(&env);
+            let token_client = token::Client::new(&env, &asset_address);
+            token_client.transfer(&from, &to, &amount);
+
+            Ok((from, to, amount))
+        }
+    }
+
+    fn _convert_to_shares(
+        env: &Env,
+        assets: i128,
+        rounding: Rounding,
+    ) -> Result<i128, ContractError> {
+        if assets <= 0 {
+            Ok(0) // Assume it is fine to return zero here
+        } else {
+            let tot_shares: i128 = Self::_total_shares_with_pending_fees(env)?;
+            let tot_assets: i128 = Self::total_assets(env)?;
+            let result: i128 = mul_div(
+                assets,
+                safe_add_i128(tot_shares, safe_pow(10, Self::_decimals_offset(env))),
+                safe_add_i128(tot_assets, 1),
+                rounding,
+            );
+            Ok(result)
+        }
+    }
+
+    // Called to calculate potential return of value
+    fn _convert_to_shares_simulate(
+        env: &Env,
+        assets: i128,
+        total_assets: i128,
+        total_shares: i128,
+        rounding: Rounding,
+    ) -> Result<i128, ContractError> {
+        if assets <= 0 || total_assets <= assets || total_shares <= 0 {
+            Ok(0) // Assume it is fine to return zero here
+        } else {
+            let result: i128 = mul_div(
+                assets,
+                safe_add_i128(total_shares, safe_pow(10, Self::_decimals_offset(env))),
+                safe_add_i128(total_assets, 1),
+                rounding,
+            );
+            Ok(result)
+        }
+    }
+
+    fn _convert_to_assets(
+        env: &Env,
+        shares: i128,
+        rounding: Rounding,
+    ) -> Result<i128, ContractError> {
+        if shares <= 0 {
+            Ok(0) // Assume it is fine to return zero here
+        } else {
+            let tot_shares: i128 = Self::_total_shares_with_pending_fees(env)?;
+            let tot_assets: i128 = Self::total_assets(env)?;
+            let result: i128 = mul_div(
+                shares,
+                safe_add_i128(tot_assets, 1),
+                safe_add_i128(tot_shares, safe_pow(10, Self::_decimals_offset(env))),
+                rounding,
+            );
+            Ok(result)
+        }
+    }
+
+    // Called to calculate potential return of value
+    fn _convert_to_assets_simulate(
+        env: &Env,
+        shares: i128,
+        total_shares: i128,
+        total_assets: i128,
+        rounding: Rounding,
+    ) -> Result<i128, ContractError> {
+        if shares <= 0 || total_shares <= shares || total_assets <= 0 {
+            Ok(0) // Assume it is fine to return zero here
+        } else {
+            let result: i128 = mul_div(
+                shares,
+                safe_add_i128(total_assets, 1),
+                safe_add_i128(total_shares, safe_pow(10, Self::_decimals_offset(env))),
+                rounding,
+            );
+            Ok(result)
+        }
+    }
+
+    /*
+       While multiple users can call the Soroban's contract concurrently, each transaction's storage operations are atomic.
+       The contract's storage can only be in a valid state before and after each transaction.
+       If two transactions try to modify the same storage simultaneously, Soroban's consensus mechanism ensures they're processed sequentially.
+
+       In Soroban, storage operations within a single contract invocation are atomic - the entire transaction either succeeds or fails.
+       However, it's still important to handle concurrent invocations properly.
+    */
+
+    fn _mint_shares(_env: &Env, _receiver: &Address, _shares: i128) -> () {
+        let current_total = read_total_shares(&_env);
+        let receiver_shares = read_total_shares_of(&_env, _receiver.clone());
+        write_total_shares(&_env, &safe_add_i128(current_total, _shares));
+        Self::_write_shares_of(_env, _receiver, safe_add_i128(receiver_shares, _shares));
+        Self::_emit_mint_event(_env, _receiver, _shares);
+    }
+
+    fn _burn_shares(_env: &Env, _owner: &Address, _shares: i128) -> () {
+        let owner_shares = read_total_shares_of(&_env, _owner.clone());
+        let current_total = read_total_shares(&_env);
+        write_total_shares(&_env, &safe_sub_i128(current_total, _shares));
+        Self::_write_shares_of(_env, _owner, safe_sub_i128(owner_shares, _shares));
+        Self::_emit_burn_event(_env, _owner, _shares);
+    }
+
+    fn _write_shares_of(env: &Env, holder: &Address, shares: i128) {
+        // Every balance change goes through here, so the holder registry follows zero and non-zero balances
+        write_total_shares_of(env, holder.clone(), &shares);
+        if shares == 0 {
+            Self::_remove_holder(env, holder);
+        } else {
+            Self::_add_holder(env, holder);
+        }
+    }
+
+    fn _add_holder(env: &Env, holder: &Address) {
+        // The vault's own balance holds the dead shares only, it is not a holder to list
+        if *holder == Self::contract_address(env) {
+            return;
+        }
+        if read_holder_index(env, holder.clone()).is_none() {
+            let count: u32 = read_holder_count(env);
+            write_holder_at(env, count, holder);
+            write_holder_index(env, holder.clone(), &count);
+            write_holder_count(env, &(count + 1));
+        }
+    }
+
+    fn _remove_holder(env: &Env, holder: &Address) {
+        // The last holder takes the free position, so positions stay contiguous
+        if let Some(position) = read_holder_index(env, holder.clone()) {
+            let last: u32 = read_holder_count(env) - 1;
+            if position != last {
+                let moved: Address = read_holder_at(env, last);
+                write_holder_at(env, position, &moved);
+                write_holder_index(env, moved, &position);
+            }
+            remove_holder_at(env, last);
+            remove_holder_index(env, holder.clone());
+            write_holder_count(env, &last);
+        }
+    }
+
+    fn _transfer_shares(
+        env: &Env,
+        from: &Address,
+        to: &Address,
+        shares: i128,
+    ) -> Result<(), VaultError> {
+        // Assume that the owner of the shares (or the spender of an allowance) is already authorized here
+        if shares < 0 {
+            return Err(VaultError::InvalidAmount);
+        }
+        let from_shares: i128 = read_total_shares_of(env, from.clone());
+        if from_shares < shares {
+            return Err(VaultError::InvalidAmount);
+        }
+        Self::_ensure_not_frozen(env, from)?;
+        Self::_ensure_not_frozen(env, to)?;
+        Self::_ensure_allowlisted(env, to)?;
+        // Total shares remain unchanged, transfer to self only emits the event
+        if from != to {
+            let to_shares: i128 = read_total_shares_of(env, to.clone());
+            Self::_write_shares_of(env, from, safe_sub_i128(from_shares, shares));
+            Self::_write_shares_of(env, to, safe_add_i128(to_shares, shares));
+        }
+        Self::_emit_transfer_event(env, from, to, shares);
+        Ok(())
+    }
+
+    fn _burn(env: &Env, from: &Address, shares: i128) -> Result<(), VaultError> {
+        // Burning shares without withdrawing assets leaves the assets to the remaining holders
+        if shares < 0 {
+            return Err(VaultError::InvalidAmount);
+        }
+        if read_total_shares_of(env, from.clone()) < shares {
+            return Err(VaultError::InvalidAmount);
+        }
+        Self::_ensure_not_frozen(env, from)?;
+        Self::_burn_shares(env, from, shares);
+        Ok(())
+    }
+
+    fn _ensure_initialized(env: &Env) {
+        if !has_administrator(env) {
+            panic_with_error!(env, ContractError::NotInitialized);
+        }
+    }
+
+    fn _unwrap_or_panic<T, E: Into<soroban_sdk::Error>>(env: &Env, result: Result<T, E>) -> T {
+        // Token interface functions cannot return errors, so the typed error is raised instead
+        match result {
+            Ok(value) => value,
+            Err(error) => panic_with_error!(env, error),
+        }
+    }
+
+    fn _prefixed_string(env: &Env, prefix: &str, value: &String) -> String {
+        let mut buffer = [0u8; 128];
+        let prefix_length: usize = prefix.len();
+        let mut total_length: usize = prefix_length + value.len() as usize;
+        buffer[..prefix_length].copy_from_slice(prefix.as_bytes());
+        if total_length <= buffer.len() {
+            value.copy_into_slice(&mut buffer[prefix_length..total_length]);
+        } else {
+            // Longer values are cut to the buffer, dropping a character that no longer fits completely
+            env.string_copy_to_slice(
+                value.to_object(),
+                Val::U32_ZERO,
+                &mut buffer[prefix_length..],
+            )
+            .unwrap();
+            total_length = buffer.len();
+            if let Err(error) = core::str::from_utf8(&buffer) {
+                if error.error_len().is_none() {
+                    total_length = error.valid_up_to();
+                }
+            }
+        }
+        String::from_bytes(env, &buffer[..total_length])
+    }
+
+    fn _ensure_contract_not_paused(env: &Env) -> Result<(), VaultError> {
+        if is_paused(env) {
+            Err(VaultError::Paused)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn _ensure_deposit_not_paused(env: &Env) -> Result<(), VaultError> {
+        if deposit_paused(env) {
+            Err(VaultError::DepositPaused)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn _ensure_withdraw_not_paused(env: &Env) -> Result<(), VaultError> {
+        if withdraw_paused(env) {
+            Err(VaultError::WithdrawPaused)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn _ensure_claim_allowed(env: &Env) -> Result<(), VaultError> {
+        // Claims pay out at the current share price, which still moves with the event during a lock.
+        // They wait until the market settled in this window or the window is over.
+        match Self::_current_lock_window(env) {
+            Some(window)
+                if read_last_settlement(env)
+                    .is_none_or(|settled| settled < window.lock_timestamp) =>
+            {
+                Err(VaultError::Locked)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn _ensure_not_locked(env: &Env) -> Result<(), VaultError> {
+        // New deposits and withdrawals are not possible while the vault is locked
+        if Self::_is_locked(env) {
+            Err(VaultError::Locked)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn _spend_allowance_unless_operator(
         env: &Env,
+        owner: &Address,
+        caller: &Address,
         shares: i128,
-        rounding: Rounding,
-    ) -> Result<i128, ContractError> {
-        if shares <= 0 {
-            Ok(0) // Assume it is fine to return zero here
+    ) -> Result<(), VaultError> {
+        if caller == owner || is_operator(env, owner.clone(), caller.clone()) {
+            Ok(())
         } else {
-            let tot_shares: i128 = Self::total_shares(env)?;
-            let tot_assets: i128 = Self::total_assets(env)?;
-            let result: i128 = mul_div(
-                shares,
-                safe_add_i128(tot_assets, 1),
-                safe_add_i128(tot_shares, safe_pow(10, Self::_decimals_offset())),
-                rounding,
-            );
-            Ok(result)
+            _spend_allowance(env, owner, caller, shares)
         }
     }
 
-    // Called to calculate potential return of value
-    fn _convert_to_assets_simulate(
-        shares: i128,
-        total_shares: i128,
-        total_assets: i128,
-        rounding: Rounding,
-    ) -> Result<i128, ContractError> {
-        if shares <= 0 || total_shares <= shares || total_assets <= 0 {
-            Ok(0) // Assume it is fine to return zero here
+    fn _ensure_before_deadline(env: &Env, deadline: u64) -> Result<(), VaultError> {
+        // The deadline is inclusive, transactions that are included later must not execute
+        if env.ledger().timestamp() > deadline {
+            Err(VaultError::DeadlineExpired)
         } else {
-            let result: i128 = mul_div(
-                shares,
-                safe_add_i128(total_assets, 1),
-                safe_add_i128(total_shares, safe_pow(10, Self::_decimals_offset())),
-                rounding,
-            );
-            Ok(result)
+            Ok(())
         }
     }
 
-    /*
-       While multiple users can call the Soroban's contract concurrently, each transaction's storage operations are atomic.
-       The contract's storage can only be in a valid state before and after each transaction.
-       If two transactions try to modify the same storage simultaneously, Soroban's consensus mechanism ensures they're processed sequentially.
+    fn _has_role(env: &Env, role: Role, account: &Address) -> bool {
+        match role {
+            Role::Owner => *account == read_administrator(env),
+            _ => has_role(env, role, account.clone()),
+        }
+    }
 
-       In Soroban, storage operations within a single contract invocation are atomic - the entire transaction either succeeds or fails.
-       However, it's still important to handle concurrent invocations properly.
-    */
+    fn _authorize(env: &Env, caller: &Address, roles: &[Role]) -> bool {
+        // Caller must sign and hold at least one of the roles
+        caller.require_auth();
+        roles.iter().any(|role| Self::_has_role(env, *role, caller))
+    }
 
-    fn _mint_shares(_env: &Env, _receiver: &Address, _shares: i128) -> () {
-        let current_total = read_total_shares(&_env);
-        let receiver_shares = read_total_shares_of(&_env, _receiver.clone());
-        write_total_shares(&_env, &safe_add_i128(current_total, _shares));
-        write_total_shares_of(
-            &_env,
-            _receiver.clone(),
-            &safe_add_i128(receiver_shares, _shares),
-        );
+    fn _require_compliance(env: &Env, caller: &Address) -> Result<(), VaultError> {
+        if Self::_authorize(env, caller, &[Role::Owner, Role::Compliance]) {
+            Ok(())
+        } else {
+            Err(VaultError::Unauthorized)
+        }
     }
 
-    fn _burn_shares(_env: &Env, _owner: &Address, _shares: i128) -> () {
-        let owner_shares = read_total_shares_of(&_env, _owner.clone());
-        let current_total = read_total_shares(&_env);
-        write_total_shares(&_env, &safe_sub_i128(current_total, _shares));
-        write_total_shares_of(&_env, _owner.clone(), &safe_sub_i128(owner_shares, _shares));
+    fn _replace_role_holder(
+        env: &Env,
+        owner: &Address,
+        role: Role,
+        previous: Option<Address>,
+        next: Option<Address>,
+    ) {
+        // Accounts set through set_market and set_compliance hold the matching role,
+        // replacing the account revokes the role from the previous one
+        if let Some(previous) = previous {
+            if next.as_ref() != Some(&previous) && has_role(env, role, previous.clone()) {
+                remove_role(env, role, previous.clone());
+                Self::_emit_role_revoked_event(env, owner, role, &previous);
+            }
+        }
+        if let Some(next) = next {
+            if !has_role(env, role, next.clone()) {
+                write_role(env, role, next.clone());
+                Self::_emit_role_granted_event(env, owner, role, &next);
+            }
+        }
+    }
+
+    fn _ensure_not_frozen(env: &Env, address: &Address) -> Result<(), VaultError> {
+        if is_frozen(env, address.clone()) {
+            Err(VaultError::AccountFrozen)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn _ensure_allowlisted(env: &Env, address: &Address) -> Result<(), VaultError> {
+        // Only enforced in allowlist mode
+        if allowlist_enabled(env) && !is_allowlisted(env, address.clone()) {
+            Err(VaultError::NotAllowlisted)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn _ensure_deposit_allowed(env: &Env) -> Result<(), VaultError> {
+        Self::_ensure_contract_not_paused(env)?;
+        Self::_ensure_deposit_not_paused(env)?;
+        Self::_ensure_not_locked(env)
+    }
+
+    fn _ensure_withdraw_allowed(env: &Env) -> Result<(), VaultError> {
+        Self::_ensure_contract_not_paused(env)?;
+        Self::_ensure_withdraw_not_paused(env)?;
+        Self::_ensure_not_locked(env)
+    }
+
+    fn _is_locked(env: &Env) -> bool {
+        Self::_current_lock_window(env).is_some()
+    }
+
+    fn _current_lock_window(env: &Env) -> Option<LockWindow> {
+        let current_timestamp: u64 = env.ledger().timestamp();
+        Self::_lock_windows(env).iter().find(|window| {
+            current_timestamp >= window.lock_timestamp
+                && current_timestamp <= window.unlock_timestamp
+        })
+    }
+
+    fn _lock_windows(env: &Env) -> Vec<LockWindow> {
+        // Vaults that are not migrated yet keep using their single lock window
+        if has_lock_windows(env) {
+            read_lock_windows(env)
+        } else {
+            let mut windows: Vec<LockWindow> = Vec::new(env);
+            if let Some(window) = read_legacy_lock_window(env) {
+                windows.push_back(window);
+            }
+            windows
+        }
+    }
+
+    fn _scheduled_lock_window(env: &Env) -> LockWindow {
+        // The current window, else the next one, else the last one that has passed
+        let now: u64 = env.ledger().timestamp();
+        let windows: Vec<LockWindow> = Self::_lock_windows(env);
+        windows
+            .iter()
+            .find(|window| now <= window.unlock_timestamp)
+            .or(windows.last())
+            .unwrap()
+    }
+
+    fn _deposits_open(env: &Env) -> bool {
+        has_administrator(env)
+            && has_base_unit_accounting(env)
+            && !is_paused(env)
+            && !deposit_paused(env)
+            && !Self::_is_locked(env)
+    }
+
+    fn _withdrawals_open(env: &Env) -> bool {
+        has_administrator(env)
+            && has_base_unit_accounting(env)
+            && !is_paused(env)
+            && !withdraw_paused(env)
+            && !Self::_is_locked(env)
+    }
+
+    fn _asset_balance(env: &Env) -> i128 {
+        let token_client = token::Client::new(env, &read_asset_address(env));
+        token_client.balance(&Self::contract_address(env))
+    }
+
+    fn _managed_assets(env: &Env) -> i128 {
+        // Vaults deployed before internal accounting fall back to the token balance until synced
+        if has_total_managed_assets(env) {
+            read_total_managed_assets(env)
+        } else {
+            Self::_asset_balance(env)
+        }
+    }
+
+    fn _increase_managed_assets(env: &Env, assets: i128) {
+        let managed: i128 = Self::_managed_assets(env);
+        write_total_managed_assets(env, &safe_add_i128(managed, assets));
+    }
+
+    fn _decrease_managed_assets(env: &Env, assets: i128) {
+        let managed: i128 = Self::_managed_assets(env);
+        write_total_managed_assets(env, &safe_sub_i128(managed, assets));
+    }
+
+    fn _settle_out(
+        env: &Env,
+        market: &Address,
+        to: &Address,
+        amount: i128,
+    ) -> Result<i128, VaultError> {
+        // Assume that the market is already authorized here
+        Self::_accrue_fees(env);
+        if amount <= 0 {
+            Err(VaultError::InvalidAmount)
+        } else if amount > Self::_managed_assets(env) {
+            Err(VaultError::InsufficientManagedAssets)
+        } else {
+            Self::_decrease_managed_assets(env, amount);
+            let token_client = token::Client::new(env, &read_asset_address(env));
+            token_client.transfer(&Self::contract_address(env), to, &amount);
+            Self::_advance_settlement_epoch(env);
+            Self::_emit_settlement_event(env, market, -amount);
+            Ok(Self::_managed_assets(env))
+        }
+    }
+
+    fn _settle_in(env: &Env, market: &Address, amount: i128) -> Result<i128, VaultError> {
+        // Assume that the market is already authorized and transferred the amount beforehand
+        Self::_accrue_fees(env);
+        if amount <= 0 {
+            Err(VaultError::InvalidAmount)
+        } else {
+            let managed: i128 = safe_add_i128(Self::_managed_assets(env), amount);
+            if managed > Self::_asset_balance(env) {
+                Err(VaultError::UnbackedSettlement)
+            } else {
+                write_total_managed_assets(env, &managed);
+                Self::_advance_settlement_epoch(env);
+                Self::_emit_settlement_event(env, market, amount);
+                Ok(managed)
+            }
+        }
+    }
+
+    fn _assets_of(env: &Env, owner: &Address) -> i128 {
+        let shares: i128 = read_total_shares_of(env, owner.clone());
+        Self::_unwrap_or_panic(env, Self::_convert_to_assets(env, shares, Rounding::Floor))
+    }
+
+    fn _check_deposit_limits(
+        env: &Env,
+        receiver: &Address,
+        assets: i128,
+    ) -> Result<(), VaultError> {
+        if read_min_deposit(env).is_some_and(|min| assets < min) {
+            return Err(VaultError::DepositBelowMinimum);
+        }
+        if let Some(cap) = read_total_assets_cap(env) {
+            if safe_add_i128(Self::_managed_assets(env), assets) > cap {
+                return Err(VaultError::ExceededTotalAssetsCap);
+            }
+        }
+        // The account cap is advisory, it bounds what one receiver holds after a deposit, but shares
+        // can be moved on and deposits spread over several accounts. The allowlist limits who can hold shares.
+        if let Some(cap) = read_account_deposit_cap(env) {
+            if safe_add_i128(Self::_assets_of(env, receiver), assets) > cap {
+                return Err(VaultError::ExceededAccountCap);
+            }
+        }
+        Ok(())
+    }
+
+    fn _virtual_total_shares(env: &Env, total_shares: i128) -> i128 {
+        safe_add_i128(total_shares, safe_pow(10, Self::_decimals_offset(env)))
+    }
+
+    fn _current_share_price(env: &Env) -> i128 {
+        let total_shares: i128 = read_total_shares(env);
+        _share_price(
+            Self::_managed_assets(env),
+            Self::_virtual_total_shares(env, total_shares),
+        )
+    }
+
+    fn _total_shares_with_pending_fees(env: &Env) -> Result<i128, ContractError> {
+        // Conversions and previews account for fee shares that are not minted yet
+        let (_, _, fee_shares) = Self::_pending_fee_accrual(env);
+        Ok(safe_add_i128(Self::total_shares(env)?, fee_shares))
     }
 
-    fn _ensure_contract_not_paused(_env: &Env) {
-        if is_paused(_env) {
-            panic!("Contract is currently paused!");
+    // Returns the management fee, the performance fee (in assets) and the shares to mint for them
+    fn _pending_fee_accrual(env: &Env) -> (i128, i128, i128) {
+        let total_shares: i128 = read_total_shares(env);
+        if total_shares <= 0 || read_treasury(env).is_none() {
+            return (0, 0, 0);
         }
+        let now: u64 = env.ledger().timestamp();
+        let elapsed: u64 = now.saturating_sub(read_last_fee_accrual(env).unwrap_or(now));
+        let total_assets: i128 = Self::_managed_assets(env);
+        let virtual_shares: i128 = Self::_virtual_total_shares(env, total_shares);
+        let management_fee: i128 =
+            _management_fee(total_assets, read_management_fee_basis_points(env), elapsed);
+        let share_price: i128 = _share_price(total_assets, virtual_shares);
+        let performance_fee: i128 = match read_high_water_mark(env) {
+            Some(high_water_mark) => _performance_fee(
+                share_price,
+                high_water_mark,
+                virtual_shares,
+                read_performance_fee_basis_points(env),
+            ),
+            None => 0,
+        };
+        let fee_shares: i128 = _fee_shares(
+            safe_add_i128(management_fee, performance_fee),
+            total_assets,
+            virtual_shares,
+        );
+        (management_fee, performance_fee, fee_shares)
     }
 
-    fn _ensure_deposit_not_paused(_env: &Env) {
-        if deposit_paused(_env) {
-            panic!("Deposit is currently paused!");
+    fn _accrue_fees(env: &Env) -> i128 {
+        let (management_fee, performance_fee, fee_shares) = Self::_pending_fee_accrual(env);
+        if fee_shares > 0 {
+            let treasury: Address = read_treasury(env).unwrap();
+            Self::_mint_shares(env, &treasury, fee_shares);
+            Self::_emit_fees_accrued_event(
+                env,
+                &treasury,
+                management_fee,
+                performance_fee,
+                fee_shares,
+            );
+        }
+        write_last_fee_accrual(env, &env.ledger().timestamp());
+        // The mark only moves up, so a loss has to be recovered before performance fees are charged again
+        let share_price: i128 = Self::_current_share_price(env);
+        if read_high_water_mark(env).is_none_or(|mark| share_price > mark) {
+            write_high_water_mark(env, &share_price);
         }
+        fee_shares
     }
 
-    fn _ensure_withdraw_not_paused(_env: &Env) {
-        if withdraw_paused(_env) {
-            panic!("Withdraw is currently paused!");
+    fn _redeem_request(env: &Env, controller: &Address) -> RedeemRequest {
+        // Pending shares become claimable once their lock window is over or a later settlement happened,
+        // even when another window has started since
+        let epoch: u32 = read_settlement_epoch(env);
+        let mut request: RedeemRequest =
+            read_redeem_request(env, controller.clone()).unwrap_or(RedeemRequest {
+                pending_shares: 0,
+                claimable_shares: 0,
+                epoch,
+                unlock_timestamp: 0,
+            });
+        if request.pending_shares > 0
+            && (request.epoch < epoch || env.ledger().timestamp() > request.unlock_timestamp)
+        {
+            request.claimable_shares =
+                safe_add_i128(request.claimable_shares, request.pending_shares);
+            request.pending_shares = 0;
         }
+        request
     }
 
-    fn _ensure_not_locked(_env: &Env) {
-        let current_timestamp: u64 = _env.ledger().timestamp();
-        let lock_timestamp: u64 = read_lock_timestamp(&_env);
-        let unlock_timestamp: u64 = read_unlock_timestamp(&_env);
-        if current_timestamp >= lock_timestamp && current_timestamp <= unlock_timestamp {
-            panic!("New deposits and withdrawals are not possible as vault is currently locked!");
+    fn _advance_settlement_epoch(env: &Env) {
+        write_settlement_epoch(env, &(read_settlement_epoch(env) + 1));
+        write_last_settlement(env, &env.ledger().timestamp());
+    }
+
+    fn _migrate_from(env: &Env, version: u32) {
+        match version {
+            // Start tracking assets internally from the current token balance, fee accrual starts now.
+            // Balances and allowances in instance storage are moved lazily, on their next write.
+            0 => {
+                if !has_total_managed_assets(env) {
+                    write_total_managed_assets(env, &Self::_asset_balance(env));
+                }
+                if read_last_fee_accrual(env).is_none() {
+                    write_last_fee_accrual(env, &env.ledger().timestamp());
+                }
+            }
+            // The single lock window becomes the first entry of the lock schedule
+            1 => {
+                if !has_lock_windows(env) {
+                    write_lock_windows(env, &Self::_lock_windows(env));
+                }
+                remove_legacy_lock_window(env);
+            }
+            _ => panic_with_error!(env, ContractError::AlreadyMigrated),
         }
     }
 
+    fn _seed_dead_shares(env: &Env, admin: &Address, assets: i128) -> Result<(), ContractError> {
+        // Shares minted to the vault's own balance have no owner who could redeem or transfer them,
+        // so a donation can no longer round later deposits down to nothing.
+        // Shares escrowed for redeem requests are tracked apart and never mix with these.
+        let shares: i128 = Self::_convert_to_shares(env, assets, Rounding::Floor)?;
+        let vault_address: Address = Self::contract_address(env);
+        let token_client = token::Client::new(env, &read_asset_address(env));
+        token_client.transfer(admin, &vault_address, &assets);
+        Self::_increase_managed_assets(env, assets);
+        Self::_mint_shares(env, &vault_address, shares);
+        Self::_emit_deposit_event(env, admin, &vault_address, assets, shares);
+        Ok(())
+    }
+
     fn _deposit(
         _env: &Env,
         _caller: &Address,
         _receiver: &Address,
         _assets: i128,
         _shares: i128,
-    ) -> () {
+    ) -> Result<(), VaultError> {
         // Assume that here we receive already valid parameters, i.e. caller is authorized, amounts are validated and so on
-        Self::_ensure_contract_not_paused(_env);
-        Self::_ensure_deposit_not_paused(_env);
-        Self::_ensure_not_locked(_env);
+        Self::_ensure_deposit_allowed(_env)?;
         let asset_address: Address = read_asset_address(_env);
         let token_client = token::Client::new(_env, &asset_address);
-        let result: i128 = Self::_multiply_by_decimals(&_env, _assets);
-        let balance: i128 = token_client.balance(&_caller);
-        if balance < result {
-            panic!("Insufficient balance")
+        let balance: i128 = token_client.balance(_caller);
+        if balance < _assets {
+            return Err(VaultError::InsufficientAssetBalance);
         }
         // Transfer underlying assets from caller to vault
         // This must happen before minting shares to prevent reentrancy issues
-        token_client.transfer(&_caller, &Self::contract_address(_env), &result);
+        token_client.transfer(_caller, &Self::contract_address(_env), &_assets);
+        // Entry fee is included in the deposited assets and forwarded to the fee recipient
+        let fee: i128 = _fee_on_total(_assets, read_entry_fee_basis_points(_env));
+        Self::_increase_managed_assets(_env, safe_sub_i128(_assets, fee));
+        if fee > 0 {
+            let recipient: Address = read_fee_recipient(_env).unwrap();
+            token_client.transfer(&Self::contract_address(_env), &recipient, &fee);
+            Self::_emit_entry_fee_event(_env, _caller, &recipient, fee);
+        }
         // Mint new share tokens to receiver, update total shares and receiver's shares
         Self::_mint_shares(&_env, &_receiver, _shares);
         // Emit event
         Self::_emit_deposit_event(_env, _caller, _receiver, _assets, _shares);
+        Ok(())
     }
 
     fn _withdraw(
@@ -802,29 +2778,42 @@ impl Vault {
         _owner: &Address,
         _assets: i128,
         _shares: i128,
-    ) -> () {
+    ) -> Result<(), VaultError> {
         // Assume that here we receive already valid parameters, i.e. caller is authorized, amounts are validated and so on
-        Self::_ensure_contract_not_paused(_env);
-        Self::_ensure_withdraw_not_paused(_env);
-        Self::_ensure_not_locked(_env);
-        // Spend allowance
-        if _caller != _owner {
-            _spend_allowance(&_env, &_owner, &_caller, _shares).unwrap();
-        }
-        let asset_address: Address = read_asset_address(_env);
-        let token_client = token::Client::new(_env, &asset_address);
-        let result: i128 = Self::_multiply_by_decimals(&_env, _assets);
-        let balance: i128 = token_client.balance(&Self::contract_address(_env));
-        if balance < result {
-            panic!("Insufficient balance")
-        }
+        Self::_ensure_withdraw_allowed(_env)?;
+        // Spend allowance, approved operators do not need one
+        Self::_spend_allowance_unless_operator(_env, _owner, _caller, _shares)?;
         // Burn share tokens from owner, update total shares and owner's shares
         // This must happen before transferring assets to prevent reentrancy
         Self::_burn_shares(&_env, _owner, _shares);
-        // Transfer underlying assets from vault to receiver
-        token_client.transfer(&Self::contract_address(_env), &_receiver, &result);
+        Self::_pay_out(_env, _owner, _receiver, _assets)?;
         // Emit event
         Self::_emit_withdraw_event(_env, _caller, _receiver, _owner, _assets, _shares);
+        Ok(())
+    }
+
+    fn _pay_out(
+        env: &Env,
+        owner: &Address,
+        receiver: &Address,
+        assets: i128,
+    ) -> Result<(), VaultError> {
+        // Shares must already be burned here, the exit fee is paid on top of the withdrawn assets
+        let token_client = token::Client::new(env, &read_asset_address(env));
+        let fee: i128 = _fee_on_raw(assets, read_exit_fee_basis_points(env));
+        let balance: i128 = token_client.balance(&Self::contract_address(env));
+        if balance < safe_add_i128(assets, fee) {
+            return Err(VaultError::InsufficientVaultLiquidity);
+        }
+        Self::_decrease_managed_assets(env, safe_add_i128(assets, fee));
+        // Transfer underlying assets from vault to receiver
+        token_client.transfer(&Self::contract_address(env), receiver, &assets);
+        if fee > 0 {
+            let recipient: Address = read_fee_recipient(env).unwrap();
+            token_client.transfer(&Self::contract_address(env), &recipient, &fee);
+            Self::_emit_exit_fee_event(env, owner, &recipient, fee);
+        }
+        Ok(())
     }
 
     fn _emit_initialized_event(
@@ -840,11 +2829,208 @@ impl Vault {
             .publish(topics, (asset, name, symbol, decimals));
     }
 
-    fn _emit_transfer_shares_event(env: &Env, owner: &Address, receiver: &Address, shares: i128) {
-        let topics = (symbol_short!("shares"), owner, receiver);
+    fn _emit_admin_proposed_event(env: &Env, admin: &Address, new_admin: &Address) {
+        let topics = (symbol_short!("adm_prop"), admin, new_admin);
+        env.events().publish(topics, ());
+    }
+
+    fn _emit_admin_accepted_event(env: &Env, previous_admin: &Address, new_admin: &Address) {
+        let topics = (symbol_short!("adm_acpt"), previous_admin, new_admin);
+        env.events().publish(topics, ());
+    }
+
+    fn _emit_admin_cancelled_event(env: &Env, admin: &Address, pending_admin: &Address) {
+        let topics = (symbol_short!("adm_cncl"), admin, pending_admin);
+        env.events().publish(topics, ());
+    }
+
+    fn _emit_upgraded_event(env: &Env, admin: &Address, new_wasm_hash: BytesN<32>) {
+        let topics = (symbol_short!("upgrade"), admin);
+        env.events().publish(topics, new_wasm_hash);
+    }
+
+    fn _emit_schema_migrated_event(env: &Env, admin: &Address, previous: u32, current: u32) {
+        let topics = (symbol_short!("schema"), admin);
+        env.events().publish(topics, (previous, current));
+    }
+
+    fn _emit_base_units_migrated_event(
+        env: &Env,
+        admin: &Address,
+        scale: i128,
+        total_shares: i128,
+    ) {
+        let topics = (symbol_short!("migrate"), admin);
+        env.events().publish(topics, (scale, total_shares));
+    }
+
+    fn _emit_transfer_event(env: &Env, from: &Address, to: &Address, shares: i128) {
+        let topics = (symbol_short!("transfer"), from, to);
+        env.events().publish(topics, shares);
+    }
+
+    fn _emit_mint_event(env: &Env, to: &Address, shares: i128) {
+        // Shares are minted by the vault itself, so it takes the admin position of the standard event
+        let topics = (symbol_short!("mint"), env.current_contract_address(), to);
+        env.events().publish(topics, shares);
+    }
+
+    fn _emit_burn_event(env: &Env, from: &Address, shares: i128) {
+        let topics = (symbol_short!("burn"), from);
+        env.events().publish(topics, shares);
+    }
+
+    fn _emit_sync_event(env: &Env, admin: &Address, previous: i128, current: i128) {
+        let topics = (symbol_short!("sync"), admin);
+        env.events().publish(topics, (previous, current));
+    }
+
+    fn _emit_skim_event(env: &Env, to: &Address, assets: i128) {
+        let topics = (symbol_short!("skim"), to);
+        env.events().publish(topics, assets);
+    }
+
+    fn _emit_deposit_limits_event(
+        env: &Env,
+        admin: &Address,
+        total_assets_cap: Option<i128>,
+        account_cap: Option<i128>,
+        min_deposit: Option<i128>,
+    ) {
+        let topics = (symbol_short!("limits"), admin);
+        env.events()
+            .publish(topics, (total_assets_cap, account_cap, min_deposit));
+    }
+
+    fn _emit_fees_updated_event(
+        env: &Env,
+        admin: &Address,
+        recipient: &Address,
+        entry_fee_basis_points: u32,
+        exit_fee_basis_points: u32,
+    ) {
+        let topics = (symbol_short!("fees"), admin, recipient);
+        env.events()
+            .publish(topics, (entry_fee_basis_points, exit_fee_basis_points));
+    }
+
+    fn _emit_entry_fee_event(env: &Env, payer: &Address, recipient: &Address, fee: i128) {
+        let topics = (symbol_short!("entryfee"), payer, recipient);
+        env.events().publish(topics, fee);
+    }
+
+    fn _emit_exit_fee_event(env: &Env, payer: &Address, recipient: &Address, fee: i128) {
+        let topics = (symbol_short!("exitfee"), payer, recipient);
+        env.events().publish(topics, fee);
+    }
+
+    fn _emit_accrual_fees_updated_event(
+        env: &Env,
+        admin: &Address,
+        treasury: &Address,
+        management_fee_basis_points: u32,
+        performance_fee_basis_points: u32,
+    ) {
+        let topics = (symbol_short!("feeaccrl"), admin, treasury);
+        env.events().publish(
+            topics,
+            (management_fee_basis_points, performance_fee_basis_points),
+        );
+    }
+
+    fn _emit_fees_accrued_event(
+        env: &Env,
+        treasury: &Address,
+        management_fee: i128,
+        performance_fee: i128,
+        shares: i128,
+    ) {
+        let topics = (symbol_short!("accrue"), treasury);
+        env.events()
+            .publish(topics, (management_fee, performance_fee, shares));
+    }
+
+    fn _emit_freeze_event(env: &Env, admin: &Address, address: &Address, frozen: bool) {
+        let topics = (symbol_short!("freeze"), admin, address);
+        env.events().publish(topics, frozen);
+    }
+
+    fn _emit_role_granted_event(env: &Env, owner: &Address, role: Role, account: &Address) {
+        let topics = (symbol_short!("rolegrant"), role, account);
+        env.events().publish(topics, owner.clone());
+    }
+
+    fn _emit_role_revoked_event(env: &Env, owner: &Address, role: Role, account: &Address) {
+        let topics = (symbol_short!("rolerevok"), role, account);
+        env.events().publish(topics, owner.clone());
+    }
+
+    fn _emit_compliance_event(env: &Env, admin: &Address, compliance: Option<Address>) {
+        let topics = (symbol_short!("complnce"), admin);
+        env.events().publish(topics, compliance);
+    }
+
+    fn _emit_allowlist_mode_event(env: &Env, admin: &Address, enabled: bool) {
+        let topics = (symbol_short!("allowlist"), admin);
+        env.events().publish(topics, enabled);
+    }
+
+    fn _emit_allowlisted_event(env: &Env, caller: &Address, address: &Address, allowed: bool) {
+        let topics = (symbol_short!("allowed"), caller, address);
+        env.events().publish(topics, allowed);
+    }
+
+    fn _emit_redeem_request_event(
+        env: &Env,
+        caller: &Address,
+        controller: &Address,
+        owner: &Address,
+        shares: i128,
+    ) {
+        let topics = (symbol_short!("redeemreq"), caller, controller, owner);
         env.events().publish(topics, shares);
     }
 
+    fn _emit_lock_window_event(env: &Env, caller: &Address, window: LockWindow) {
+        let topics = (symbol_short!("lockwin"), caller);
+        env.events()
+            .publish(topics, (window.lock_timestamp, window.unlock_timestamp));
+    }
+
+    fn _emit_lock_window_rescheduled_event(
+        env: &Env,
+        caller: &Address,
+        previous: LockWindow,
+        window: LockWindow,
+    ) {
+        let topics = (symbol_short!("lockresch"), caller);
+        env.events().publish(
+            topics,
+            (
+                previous.lock_timestamp,
+                previous.unlock_timestamp,
+                window.lock_timestamp,
+                window.unlock_timestamp,
+            ),
+        );
+    }
+
+    fn _emit_operator_event(env: &Env, owner: &Address, operator: &Address, approved: bool) {
+        let topics = (symbol_short!("operator"), owner, operator);
+        env.events().publish(topics, approved);
+    }
+
+    fn _emit_permit_signer_event(env: &Env, owner: &Address, public_key: Option<BytesN<32>>) {
+        let topics = (symbol_short!("signer"), owner);
+        env.events().publish(topics, public_key);
+    }
+
+    fn _emit_settlement_event(env: &Env, market: &Address, assets: i128) {
+        // Positive amount for collateral received, negative for collateral paid out
+        let topics = (symbol_short!("settle"), market);
+        env.events().publish(topics, assets);
+    }
+
     fn _emit_deposit_event(
         env: &Env,
         caller: &Address,
@@ -868,7 +3054,8 @@ impl Vault {
         env.events().publish(topics, (assets, shares));
     }
 
-    fn _decimals_offset() -> u32 {
-        0
+    fn _decimals_offset(env: &Env) -> u32 {
+        // Virtual shares, i.e. 10^offset shares per virtual asset, make inflating the share price by donation unprofitable
+        read_decimals_offset(env)
     }
 }